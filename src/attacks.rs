@@ -0,0 +1,474 @@
+use std::sync::OnceLock;
+
+use crate::board::BitBoard;
+use crate::board::Square;
+
+const KNIGHT_DELTAS: [(i32, i32); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_DELTAS: [(i32, i32); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+const WHITE_PAWN_DELTAS: [(i32, i32); 2] = [(-1, 1), (1, 1)];
+const BLACK_PAWN_DELTAS: [(i32, i32); 2] = [(-1, -1), (1, -1)];
+
+// The tables are built by the compiler, so looking up an attack is a single array access.
+static KNIGHT_ATTACKS: [BitBoard; 64] = init_leaper_table(&KNIGHT_DELTAS);
+static KING_ATTACKS: [BitBoard; 64] = init_leaper_table(&KING_DELTAS);
+// First table corresponds to WHITE pawns, second to BLACK pawns.
+static PAWN_ATTACKS: [[BitBoard; 64]; 2] = [
+    init_leaper_table(&WHITE_PAWN_DELTAS),
+    init_leaper_table(&BLACK_PAWN_DELTAS),
+];
+
+// Sliding attacks depend on the occupancy, so they are looked up with magic bitboards.
+// The magic numbers are found by a seeded search the first time a slider attack is needed.
+static SLIDING_TABLES: OnceLock<SlidingTables> = OnceLock::new();
+const MAGIC_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    // Squares whose occupancy can change the attacks, without the board edges.
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    // Start of this square's slice in the shared attack table.
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: u64) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct SlidingTables {
+    rook_magics: [Magic; 64],
+    bishop_magics: [Magic; 64],
+    attacks: Vec<BitBoard>,
+}
+
+pub struct Attacks;
+
+impl Attacks {
+    // Squares attacked by a knight on 'square'.
+    pub fn knight(square: Square) -> BitBoard {
+        KNIGHT_ATTACKS[square.to_usize()]
+    }
+
+    // Squares attacked by a king on 'square'.
+    pub fn king(square: Square) -> BitBoard {
+        KING_ATTACKS[square.to_usize()]
+    }
+
+    // Squares attacked by a pawn of 'side' on 'square'.
+    pub fn pawn(square: Square, side: usize) -> BitBoard {
+        PAWN_ATTACKS[side][square.to_usize()]
+    }
+
+    // Squares attacked by a bishop on 'square'. Attacks include the first blocker on each ray.
+    pub fn bishop(square: Square, occupied: BitBoard) -> BitBoard {
+        let tables = Attacks::sliding_tables();
+        tables.attacks[tables.bishop_magics[square.to_usize()].index(occupied.0)]
+    }
+
+    // Squares attacked by a rook on 'square'. Attacks include the first blocker on each ray.
+    pub fn rook(square: Square, occupied: BitBoard) -> BitBoard {
+        let tables = Attacks::sliding_tables();
+        tables.attacks[tables.rook_magics[square.to_usize()].index(occupied.0)]
+    }
+
+    // Squares attacked by a queen on 'square'. Attacks include the first blocker on each ray.
+    pub fn queen(square: Square, occupied: BitBoard) -> BitBoard {
+        Attacks::bishop(square, occupied) | Attacks::rook(square, occupied)
+    }
+
+    // The squares strictly between 'a' and 'b' if they share a rank, file or diagonal, and
+    // no squares otherwise.
+    pub fn between(a: Square, b: Square) -> BitBoard {
+        let attacks = match Attacks::slider_through(a, b) {
+            Some(attacks) => attacks,
+            None => return BitBoard::empty(),
+        };
+        attacks(a, BitBoard::from_square(b)) & attacks(b, BitBoard::from_square(a))
+    }
+
+    // The whole rank, file or diagonal through 'a' and 'b', from edge to edge, or no squares
+    // if they don't share one.
+    pub fn line(a: Square, b: Square) -> BitBoard {
+        let attacks = match Attacks::slider_through(a, b) {
+            Some(attacks) => attacks,
+            None => return BitBoard::empty(),
+        };
+        (attacks(a, BitBoard::empty()) & attacks(b, BitBoard::empty()))
+            | BitBoard::from_square(a)
+            | BitBoard::from_square(b)
+    }
+
+    // The attacks of the slider that moves from 'a' to 'b' on an empty board, if any does.
+    fn slider_through(a: Square, b: Square) -> Option<fn(Square, BitBoard) -> BitBoard> {
+        if Attacks::rook(a, BitBoard::empty()).is_bit_set(b) {
+            Some(Attacks::rook)
+        } else if Attacks::bishop(a, BitBoard::empty()).is_bit_set(b) {
+            Some(Attacks::bishop)
+        } else {
+            None
+        }
+    }
+
+    // Builds the sliding attack tables up front, so the first search doesn't pay for it.
+    pub fn init() {
+        Attacks::sliding_tables();
+    }
+
+    fn sliding_tables() -> &'static SlidingTables {
+        SLIDING_TABLES.get_or_init(|| {
+            let mut random = Xorshift(MAGIC_SEED);
+            let mut attacks = Vec::new();
+            let rook_magics = find_magics(&ROOK_DIRECTIONS, &mut attacks, &mut random);
+            let bishop_magics = find_magics(&BISHOP_DIRECTIONS, &mut attacks, &mut random);
+            SlidingTables {
+                rook_magics,
+                bishop_magics,
+                attacks,
+            }
+        })
+    }
+}
+
+// Finds a magic number for every square and appends each square's attacks to 'attacks'.
+fn find_magics(
+    directions: &[(i32, i32)],
+    attacks: &mut Vec<BitBoard>,
+    random: &mut Xorshift,
+) -> [Magic; 64] {
+    let mut magics = [Magic::default(); 64];
+    for (square, magic) in magics.iter_mut().enumerate() {
+        let mask = relevant_occupancy_mask(square, directions);
+        let bits = mask.count_ones();
+        let size = 1 << bits;
+
+        // Enumerate every subset of the mask with the carry-rippler trick.
+        let mut occupancies = Vec::with_capacity(size);
+        let mut reference = Vec::with_capacity(size);
+        let mut subset: u64 = 0;
+        loop {
+            occupancies.push(subset);
+            reference.push(sliding_attacks_slow(square, subset, directions));
+            subset = subset.wrapping_sub(mask) & mask;
+            if subset == 0 {
+                break;
+            }
+        }
+
+        // 'epoch' marks which entries were written by the current candidate, so the
+        // table doesn't need to be cleared between attempts.
+        let mut table = vec![0; size];
+        let mut epoch = vec![0; size];
+        let mut attempt = 0;
+        *magic = Magic {
+            mask,
+            magic: 0,
+            shift: 64 - bits,
+            offset: attacks.len(),
+        };
+        loop {
+            let candidate = random.sparse();
+            if (mask.wrapping_mul(candidate) >> 56).count_ones() < 6 {
+                continue;
+            }
+            attempt += 1;
+            let mut found = true;
+            for (occupancy, &expected) in occupancies.iter().zip(reference.iter()) {
+                let index = ((occupancy.wrapping_mul(candidate)) >> magic.shift) as usize;
+                if epoch[index] < attempt {
+                    epoch[index] = attempt;
+                    table[index] = expected;
+                } else if table[index] != expected {
+                    found = false;
+                    break;
+                }
+            }
+            if found {
+                magic.magic = candidate;
+                break;
+            }
+        }
+        attacks.extend(table.into_iter().map(BitBoard));
+    }
+    magics
+}
+
+// The squares a slider on 'square' sees on an empty board, minus the last square of each ray.
+// A piece on the edge can't block anything behind it, so it never changes the attacks.
+fn relevant_occupancy_mask(square: usize, directions: &[(i32, i32)]) -> u64 {
+    let mut mask = 0;
+    for &(file_delta, rank_delta) in directions {
+        let mut current = square;
+        while let Some(next) = offset(current, file_delta, rank_delta) {
+            if offset(next, file_delta, rank_delta).is_none() {
+                break;
+            }
+            mask |= 1 << (63 - next);
+            current = next;
+        }
+    }
+    mask
+}
+
+// Reference implementation that walks each ray until it leaves the board or hits a piece.
+fn sliding_attacks_slow(square: usize, occupied: u64, directions: &[(i32, i32)]) -> u64 {
+    let mut attacks = 0;
+    for &(file_delta, rank_delta) in directions {
+        let mut current = square;
+        while let Some(next) = offset(current, file_delta, rank_delta) {
+            let next_bit = 1 << (63 - next);
+            attacks |= next_bit;
+            if occupied & next_bit != 0 {
+                break;
+            }
+            current = next;
+        }
+    }
+    attacks
+}
+
+fn offset(square: usize, file_delta: i32, rank_delta: i32) -> Option<usize> {
+    let file = (square % 8) as i32 + file_delta;
+    let row = (square / 8) as i32 - rank_delta;
+    if (0..8).contains(&file) && (0..8).contains(&row) {
+        Some((row * 8 + file) as usize)
+    } else {
+        None
+    }
+}
+
+// Small deterministic PRNG, so the magic search finds the same numbers on every run.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Magic numbers with few set bits are far more likely to work.
+    fn sparse(&mut self) -> u64 {
+        self.next() & self.next() & self.next()
+    }
+}
+
+const fn init_leaper_table(deltas: &[(i32, i32)]) -> [BitBoard; 64] {
+    let mut table = [BitBoard(0); 64];
+    let mut square = 0;
+    while square < 64 {
+        table[square] = BitBoard(leaper_attacks(square, deltas));
+        square += 1;
+    }
+    table
+}
+
+// Deltas are given as (files towards H, ranks towards 8). Targets that fall off the board are skipped.
+const fn leaper_attacks(square: usize, deltas: &[(i32, i32)]) -> u64 {
+    let file = (square % 8) as i32;
+    let row = (square / 8) as i32;
+    let mut attacks = 0;
+    let mut i = 0;
+    while i < deltas.len() {
+        let target_file = file + deltas[i].0;
+        let target_row = row - deltas[i].1;
+        if target_file >= 0 && target_file < 8 && target_row >= 0 && target_row < 8 {
+            attacks |= 1 << (63 - (target_row * 8 + target_file));
+        }
+        i += 1;
+    }
+    attacks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Sides;
+
+    fn bitboard_of(squares: &[Square]) -> BitBoard {
+        let mut board = BitBoard::empty();
+        for &square in squares {
+            board.set_bit(square);
+        }
+        board
+    }
+
+    #[test]
+    fn test_knight_attacks() {
+        assert_eq!(
+            Attacks::knight(Square::A8),
+            bitboard_of(&[Square::B6, Square::C7])
+        );
+        assert_eq!(
+            Attacks::knight(Square::H1),
+            bitboard_of(&[Square::F2, Square::G3])
+        );
+        assert_eq!(
+            Attacks::knight(Square::D4),
+            bitboard_of(&[
+                Square::C6,
+                Square::E6,
+                Square::F5,
+                Square::F3,
+                Square::E2,
+                Square::C2,
+                Square::B3,
+                Square::B5,
+            ])
+        );
+        assert_eq!(
+            Attacks::knight(Square::A4),
+            bitboard_of(&[Square::B6, Square::C5, Square::C3, Square::B2])
+        );
+    }
+
+    #[test]
+    fn test_king_attacks() {
+        assert_eq!(
+            Attacks::king(Square::A8),
+            bitboard_of(&[Square::B8, Square::A7, Square::B7])
+        );
+        assert_eq!(
+            Attacks::king(Square::H1),
+            bitboard_of(&[Square::G1, Square::G2, Square::H2])
+        );
+        assert_eq!(
+            Attacks::king(Square::E1),
+            bitboard_of(&[Square::D1, Square::F1, Square::D2, Square::E2, Square::F2])
+        );
+        assert_eq!(Attacks::king(Square::D5).count(), 8);
+    }
+
+    #[test]
+    fn test_pawn_attacks() {
+        assert_eq!(
+            Attacks::pawn(Square::E2, Sides::WHITE),
+            bitboard_of(&[Square::D3, Square::F3])
+        );
+        assert_eq!(
+            Attacks::pawn(Square::A2, Sides::WHITE),
+            bitboard_of(&[Square::B3])
+        );
+        assert_eq!(
+            Attacks::pawn(Square::H7, Sides::BLACK),
+            bitboard_of(&[Square::G6])
+        );
+        assert_eq!(
+            Attacks::pawn(Square::E7, Sides::BLACK),
+            bitboard_of(&[Square::D6, Square::F6])
+        );
+        assert_eq!(Attacks::pawn(Square::A8, Sides::WHITE), BitBoard::empty());
+        assert_eq!(Attacks::pawn(Square::H1, Sides::BLACK), BitBoard::empty());
+    }
+
+    #[test]
+    fn test_rook_attacks() {
+        let occupied = bitboard_of(&[Square::A4, Square::C1, Square::H1]);
+        assert_eq!(
+            Attacks::rook(Square::A1, occupied),
+            bitboard_of(&[Square::A2, Square::A3, Square::A4, Square::B1, Square::C1])
+        );
+        assert_eq!(Attacks::rook(Square::H8, BitBoard::empty()).count(), 14);
+    }
+
+    #[test]
+    fn test_bishop_attacks() {
+        let occupied = bitboard_of(&[Square::F6, Square::B2, Square::C5]);
+        assert_eq!(
+            Attacks::bishop(Square::D4, occupied),
+            bitboard_of(&[
+                Square::E5,
+                Square::F6,
+                Square::C5,
+                Square::C3,
+                Square::B2,
+                Square::E3,
+                Square::F2,
+                Square::G1
+            ])
+        );
+        assert_eq!(Attacks::bishop(Square::A8, BitBoard::empty()).count(), 7);
+    }
+
+    #[test]
+    fn test_queen_attacks() {
+        assert_eq!(Attacks::queen(Square::D4, BitBoard::empty()).count(), 27);
+    }
+
+    #[test]
+    fn test_between_and_line() {
+        assert_eq!(
+            Attacks::between(Square::E1, Square::E4),
+            bitboard_of(&[Square::E2, Square::E3])
+        );
+        assert_eq!(
+            Attacks::between(Square::H8, Square::E5),
+            bitboard_of(&[Square::G7, Square::F6])
+        );
+        assert_eq!(Attacks::between(Square::E1, Square::E2), BitBoard::empty());
+        assert_eq!(Attacks::between(Square::E1, Square::F3), BitBoard::empty());
+
+        assert_eq!(
+            Attacks::line(Square::C3, Square::E5),
+            bitboard_of(&[
+                Square::A1,
+                Square::B2,
+                Square::C3,
+                Square::D4,
+                Square::E5,
+                Square::F6,
+                Square::G7,
+                Square::H8
+            ])
+        );
+        assert_eq!(Attacks::line(Square::B4, Square::G4).count(), 8);
+        assert_eq!(Attacks::line(Square::E1, Square::F3), BitBoard::empty());
+    }
+
+    #[test]
+    fn test_magic_attacks_match_slow_attacks() {
+        let mut random = Xorshift(0x1234_5678);
+        for square in 0..64 {
+            for sample in 0..200 {
+                // Alternate between sparse and dense boards.
+                let occupied = match sample % 3 {
+                    0 => random.sparse(),
+                    1 => random.next() & random.next(),
+                    _ => random.next(),
+                };
+                let sq = Square::from_usize(square).unwrap();
+                assert_eq!(
+                    Attacks::rook(sq, BitBoard(occupied)).0,
+                    sliding_attacks_slow(square, occupied, &ROOK_DIRECTIONS)
+                );
+                assert_eq!(
+                    Attacks::bishop(sq, BitBoard(occupied)).0,
+                    sliding_attacks_slow(square, occupied, &BISHOP_DIRECTIONS)
+                );
+            }
+        }
+    }
+}
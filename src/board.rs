@@ -1,5 +1,12 @@
-use crate::fen_parser::FenParser;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, Shr};
+use std::str::FromStr;
+
+use crate::attacks::Attacks;
+use crate::fen_parser::{FenDisplay, FenError, FenParser, PIECE_CHARS};
 use crate::move_generator::Move;
+use crate::zobrist::Zobrist;
 
 #[derive(PartialEq, Eq, PartialOrd, Clone, Copy, Debug, Hash)]
 pub struct BitBoard(pub u64);
@@ -32,53 +39,466 @@ impl BitBoard {
     pub fn full() -> BitBoard {
         BitBoard(u64::MAX)
     }
+
+    // A bitboard with only 'square' set
+    pub fn from_square(square: Square) -> BitBoard {
+        BitBoard(1 << (63 - Square::to_usize(square)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    // Number of set bits
+    pub fn count(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    // Square of the least significant set bit. Bit 0 is H1 and bit 63 is A8.
+    pub fn lsb(&self) -> Option<Square> {
+        if self.0 == 0 {
+            return None;
+        }
+        Square::from_usize(63 - self.0.trailing_zeros() as usize)
+    }
+
+    // Clears the least significant set bit and returns its square
+    pub fn pop_lsb(&mut self) -> Option<Square> {
+        let square = self.lsb()?;
+        self.0 &= self.0 - 1;
+        Some(square)
+    }
+
+    // Iterates over the squares of the set bits, starting from the least significant one
+    pub fn iter(&self) -> BitBoardIter {
+        BitBoardIter(*self)
+    }
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+// Draws the set squares as 'X' on a grid with rank 8 at the top, e.g. `println!("{}", bb)`.
+impl fmt::Display for BitBoard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in 0..8 {
+            write!(f, "{}", 8 - row)?;
+            for file in 0..8 {
+                let square = Square::from_usize(row * 8 + file).unwrap();
+                let c = if self.is_bit_set(square) { 'X' } else { '.' };
+                write!(f, " {}", c)?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "  a b c d e f g h")
+    }
+}
+
+pub struct BitBoardIter(BitBoard);
+
+impl Iterator for BitBoardIter {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Square> {
+        self.0.pop_lsb()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.count() as usize;
+        (count, Some(count))
+    }
+}
+
+impl ExactSizeIterator for BitBoardIter {}
+
+impl IntoIterator for BitBoard {
+    type Item = Square;
+    type IntoIter = BitBoardIter;
+
+    fn into_iter(self) -> BitBoardIter {
+        BitBoardIter(self)
+    }
+}
+
+impl BitAnd for BitBoard {
+    type Output = BitBoard;
+
+    fn bitand(self, rhs: BitBoard) -> BitBoard {
+        BitBoard(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for BitBoard {
+    type Output = BitBoard;
+
+    fn bitor(self, rhs: BitBoard) -> BitBoard {
+        BitBoard(self.0 | rhs.0)
+    }
+}
+
+impl BitXor for BitBoard {
+    type Output = BitBoard;
+
+    fn bitxor(self, rhs: BitBoard) -> BitBoard {
+        BitBoard(self.0 ^ rhs.0)
+    }
+}
+
+impl Not for BitBoard {
+    type Output = BitBoard;
+
+    fn not(self) -> BitBoard {
+        BitBoard(!self.0)
+    }
+}
+
+impl Shl<usize> for BitBoard {
+    type Output = BitBoard;
+
+    fn shl(self, rhs: usize) -> BitBoard {
+        BitBoard(self.0 << rhs)
+    }
+}
+
+impl Shr<usize> for BitBoard {
+    type Output = BitBoard;
+
+    fn shr(self, rhs: usize) -> BitBoard {
+        BitBoard(self.0 >> rhs)
+    }
+}
+
+impl BitAndAssign for BitBoard {
+    fn bitand_assign(&mut self, rhs: BitBoard) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl BitOrAssign for BitBoard {
+    fn bitor_assign(&mut self, rhs: BitBoard) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitXorAssign for BitBoard {
+    fn bitxor_assign(&mut self, rhs: BitBoard) {
+        self.0 ^= rhs.0;
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Position {
     // State of the game
     pub state: State,
     // Bitboards for both pieces on either side
     // First bitboards correspond to WHITE, second to BLACK
     pub bb_pieces: [[BitBoard; 6]; 2],
+    // Undo records of the moves made so far, most recent last
+    pub history: Vec<Undo>,
 }
+
+// Positions are compared and hashed by their pieces and state only, so the same position
+// reached by different moves is still the same position.
+impl PartialEq for Position {
+    fn eq(&self, other: &Self) -> bool {
+        self.bb_pieces == other.bb_pieces && self.state == other.state
+    }
+}
+
+impl Eq for Position {}
+
+impl Hash for Position {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bb_pieces.hash(state);
+        self.state.hash(state);
+    }
+}
+
 impl Position {
-    fn load_position_from_fen(fen: &str) -> Self {
+    pub fn load_position_from_fen(fen: &str) -> Result<Self, FenError> {
         let fen_parser: FenParser = FenParser::new();
         fen_parser.parse_fen(fen)
     }
+    // Describes the position as a FEN string.
+    pub fn to_fen(&self) -> String {
+        FenDisplay(self).to_string()
+    }
+
+    // Creates a position from its pieces and state. The Zobrist key in 'state' is ignored
+    // and computed from the rest of the position.
     pub fn new(state: State, bb_pieces: [[BitBoard; 6]; 2]) -> Self {
-        Self { state, bb_pieces }
+        let mut position = Self {
+            state,
+            bb_pieces,
+            history: Vec::new(),
+        };
+        position.state.zobrist_key = position.compute_zobrist_key();
+        position
+    }
+
+    // Recomputes the Zobrist key from scratch. make_move keeps 'state.zobrist_key' up to
+    // date incrementally, so the two should always agree.
+    pub fn compute_zobrist_key(&self) -> u64 {
+        Zobrist::key(self)
     }
 
     pub fn find_occupied_by(&self, side: usize) -> BitBoard {
         let mut pieces = BitBoard(0);
         for piece_type in 0..6 {
-            pieces.0 |= self.bb_pieces[side][piece_type].0;
+            pieces |= self.bb_pieces[side][piece_type];
         }
         pieces
     }
 
     pub fn find_empty(&self) -> BitBoard {
-        BitBoard(
-            !(Self::find_occupied_by(self, Sides::WHITE).0
-                | Self::find_occupied_by(self, Sides::BLACK).0),
-        )
+        !self.find_occupied()
     }
 
     pub fn find_occupied(&self) -> BitBoard {
-        BitBoard(
-            Self::find_occupied_by(self, Sides::WHITE).0
-                | Self::find_occupied_by(&self, Sides::BLACK).0,
-        )
+        Self::find_occupied_by(self, Sides::WHITE) | Self::find_occupied_by(self, Sides::BLACK)
+    }
+
+    // Finds the type of the piece that 'side' has on 'square', if any.
+    pub fn find_piece_on(&self, side: usize, square: Square) -> Option<usize> {
+        (0..6).find(|&piece_type| self.bb_pieces[side][piece_type].is_bit_set(square))
+    }
+
+    // Finds the pieces of 'by_side' that attack 'square' when the board holds 'occupied'.
+    // Passing a different occupancy lets callers ask what would be attacked after a move.
+    pub fn attackers_to(&self, square: Square, by_side: usize, occupied: BitBoard) -> BitBoard {
+        let pieces = &self.bb_pieces[by_side];
+        let queens = pieces[Pieces::QUEEN];
+
+        (Attacks::pawn(square, by_side ^ 1) & pieces[Pieces::PAWN])
+            | (Attacks::knight(square) & pieces[Pieces::KNIGHT])
+            | (Attacks::king(square) & pieces[Pieces::KING])
+            | (Attacks::bishop(square, occupied) & (pieces[Pieces::BISHOP] | queens))
+            | (Attacks::rook(square, occupied) & (pieces[Pieces::ROOK] | queens))
+    }
+
+    pub fn is_square_attacked(&self, square: Square, by_side: usize) -> bool {
+        !self
+            .attackers_to(square, by_side, self.find_occupied())
+            .is_empty()
+    }
+
+    // Whether the king of the side to move is attacked.
+    pub fn in_check(&self) -> bool {
+        !self.checkers().is_empty()
+    }
+
+    // Enemy pieces giving check to the king of the side to move.
+    pub fn checkers(&self) -> BitBoard {
+        let side = self.state.side_to_move;
+        match self.king_square(side) {
+            Some(king) => self.attackers_to(king, side ^ 1, self.find_occupied()),
+            None => BitBoard::empty(),
+        }
+    }
+
+    // Pieces of the side to move that can't leave the line between their king and an enemy slider.
+    pub fn pinned(&self) -> BitBoard {
+        let side = self.state.side_to_move;
+        let them = side ^ 1;
+        let king = match self.king_square(side) {
+            Some(king) => king,
+            None => return BitBoard::empty(),
+        };
+        let ours = self.find_occupied_by(side);
+        let theirs = self.find_occupied_by(them);
+        let queens = self.bb_pieces[them][Pieces::QUEEN];
+
+        // Sliders that would attack the king if none of our pieces were in the way.
+        let rook_snipers =
+            Attacks::rook(king, theirs) & (self.bb_pieces[them][Pieces::ROOK] | queens);
+        let bishop_snipers =
+            Attacks::bishop(king, theirs) & (self.bb_pieces[them][Pieces::BISHOP] | queens);
+
+        let mut pinned = BitBoard::empty();
+        for sniper in rook_snipers | bishop_snipers {
+            let blockers = Attacks::between(king, sniper) & (ours | theirs);
+            if blockers.count() == 1 && !(blockers & ours).is_empty() {
+                pinned |= blockers;
+            }
+        }
+        pinned
+    }
+
+    pub fn king_square(&self, side: usize) -> Option<Square> {
+        self.bb_pieces[side][Pieces::KING].lsb()
     }
 
     pub fn make_move(&mut self, mv: Move) {
-        self.bb_pieces[self.state.side_to_move][mv.piece].clear_bit(mv.from);
-        self.bb_pieces[self.state.side_to_move][mv.piece].set_bit(mv.to);
+        let side = self.state.side_to_move;
+        let them = side ^ 1;
+        let en_passant = self.is_en_passant(mv);
+        let captured = if en_passant {
+            Some(Pieces::PAWN)
+        } else {
+            self.find_piece_on(them, mv.to)
+        };
+        self.history.push(Undo {
+            state: self.state.clone(),
+            captured,
+        });
+        // The en passant key depends on the pawns, so it is taken out before anything moves
+        // and put back once the new en passant square and side to move are known.
+        let mut key = self.state.zobrist_key ^ Zobrist::en_passant_key(self);
+
+        if let Some(piece) = captured {
+            let capture_square = if en_passant {
+                Position::en_passant_victim(mv.to, side)
+            } else {
+                mv.to
+            };
+            self.bb_pieces[them][piece].clear_bit(capture_square);
+            key ^= Zobrist::piece(them, piece, capture_square);
+        }
+
+        self.bb_pieces[side][mv.piece].clear_bit(mv.from);
+        let placed = mv.promotion.unwrap_or(mv.piece);
+        self.bb_pieces[side][placed].set_bit(mv.to);
+        key ^= Zobrist::piece(side, mv.piece, mv.from) ^ Zobrist::piece(side, placed, mv.to);
+
+        if let Some((rook_from, rook_to)) = self.castling_rook_move(mv) {
+            self.bb_pieces[side][Pieces::ROOK].clear_bit(rook_from);
+            self.bb_pieces[side][Pieces::ROOK].set_bit(rook_to);
+            key ^= Zobrist::piece(side, Pieces::ROOK, rook_from)
+                ^ Zobrist::piece(side, Pieces::ROOK, rook_to);
+        }
+
+        let rights = &mut self.state.castling_rights;
+        key ^= Zobrist::castling(rights.rights);
+        let mut lost = rights.rights_lost_on(mv.from) | rights.rights_lost_on(mv.to);
+        if mv.piece == Pieces::KING {
+            lost |= Castling::for_side(side);
+        }
+        rights.remove(lost);
+        key ^= Zobrist::castling(rights.rights);
+
+        // A double pawn push leaves the skipped square open to en passant for one move.
+        self.state.en_passant_square =
+            if mv.piece == Pieces::PAWN && mv.from.to_usize().abs_diff(mv.to.to_usize()) == 16 {
+                Square::from_usize((mv.from.to_usize() + mv.to.to_usize()) / 2)
+            } else {
+                None
+            };
+
+        if mv.piece == Pieces::PAWN || captured.is_some() {
+            self.state.half_move_counter = 0;
+        } else {
+            self.state.half_move_counter += 1;
+        }
+        if side == Sides::BLACK {
+            self.state.fullmove_number += 1;
+        }
+        self.state.ply += 1;
+        self.state.side_to_move = them;
+        self.state.zobrist_key = key ^ Zobrist::white_to_move() ^ Zobrist::en_passant_key(self);
+    }
+
+    // Takes back 'mv', which must be the last move made on this position.
+    pub fn unmake_move(&mut self, mv: Move) {
+        let undo = self
+            .history
+            .pop()
+            .expect("unmake_move called without a move to take back");
+        self.state = undo.state;
+        let side = self.state.side_to_move;
+        let en_passant = self.is_en_passant(mv);
+
+        let placed = mv.promotion.unwrap_or(mv.piece);
+        self.bb_pieces[side][placed].clear_bit(mv.to);
+        self.bb_pieces[side][mv.piece].set_bit(mv.from);
+
+        if let Some((rook_from, rook_to)) = self.castling_rook_move(mv) {
+            self.bb_pieces[side][Pieces::ROOK].clear_bit(rook_to);
+            self.bb_pieces[side][Pieces::ROOK].set_bit(rook_from);
+        }
 
-        self.state.half_move_counter += 1;
-        self.state.side_to_move ^= 1;
+        if let Some(piece) = undo.captured {
+            let capture_square = if en_passant {
+                Position::en_passant_victim(mv.to, side)
+            } else {
+                mv.to
+            };
+            self.bb_pieces[side ^ 1][piece].set_bit(capture_square);
+        }
+    }
+
+    // A pawn moving onto the en passant square can only get there by capturing en passant.
+    fn is_en_passant(&self, mv: Move) -> bool {
+        mv.piece == Pieces::PAWN && self.state.en_passant_square == Some(mv.to)
+    }
+
+    // The square of the pawn taken by an en passant capture of 'side' landing on 'to'.
+    fn en_passant_victim(to: Square, side: usize) -> Square {
+        match side {
+            Sides::WHITE => Square::from_usize(to.to_usize() + 8).unwrap(),
+            _ => Square::from_usize(to.to_usize() - 8).unwrap(),
+        }
+    }
+
+    // The from and to squares of the rook when 'mv' castles. Castling moves are written as
+    // the king's move, so a king moving two files is castling even without the flag. The
+    // rook is the one the castling rights name, which in Chess960 can be on any file.
+    pub fn castling_rook_move(&self, mv: Move) -> Option<(Square, Square)> {
+        let two_files = mv.from.file().to_usize().abs_diff(mv.to.file().to_usize()) == 2;
+        if mv.piece != Pieces::KING || !(mv.castling || two_files) {
+            return None;
+        }
+        let right = Castling::right(self.state.side_to_move, mv.to.file() == File::G);
+        let (_, rook_to) = Castling::destinations(right);
+        Some((self.state.castling_rights.rook_square(right), rook_to))
+    }
+}
+
+// Unicode chess symbols for each piece, first for WHITE and then for BLACK.
+const PIECE_SYMBOLS: [[char; 6]; 2] = [
+    [
+        '\u{2659}', '\u{2658}', '\u{2657}', '\u{2656}', '\u{2655}', '\u{2654}',
+    ],
+    [
+        '\u{265F}', '\u{265E}', '\u{265D}', '\u{265C}', '\u{265B}', '\u{265A}',
+    ],
+];
+
+// Draws the board with rank 8 at the top, followed by the rest of the state. Pieces are
+// FEN letters, or Unicode chess symbols with the alternate flag, e.g. `println!("{:#}", p)`.
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pieces = if f.alternate() {
+            PIECE_SYMBOLS
+        } else {
+            PIECE_CHARS
+        };
+        for row in 0..8 {
+            write!(f, "{}", 8 - row)?;
+            for file in 0..8 {
+                let square = Square::from_usize(row * 8 + file).unwrap();
+                let c = (0..2)
+                    .find_map(|side| {
+                        self.find_piece_on(side, square)
+                            .map(|piece| pieces[side][piece])
+                    })
+                    .unwrap_or('.');
+                write!(f, " {}", c)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "  a b c d e f g h")?;
+        writeln!(f)?;
+
+        let side = if self.state.side_to_move == Sides::WHITE {
+            "white"
+        } else {
+            "black"
+        };
+        writeln!(f, "Side to move: {}", side)?;
+        write!(f, "Castling rights: ")?;
+        FenDisplay::write_castling_rights(f, self, false)?;
+        writeln!(f)?;
+        match self.state.en_passant_square {
+            Some(square) => write!(f, "En passant: {}", square),
+            None => write!(f, "En passant: -"),
+        }
     }
 }
 
@@ -110,9 +530,9 @@ impl Default for Position {
         let black_king: u64 =
             0b00001000_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
 
-        Self {
-            state: State::default(),
-            bb_pieces: [
+        Position::new(
+            State::default(),
+            [
                 [
                     BitBoard(white_pawns),
                     BitBoard(white_knights),
@@ -130,7 +550,7 @@ impl Default for Position {
                     BitBoard(black_king),
                 ],
             ],
-        }
+        )
     }
 }
 
@@ -149,19 +569,203 @@ pub enum Square {
 }
 
 impl Square {
+    // Every square, in the same order as their numbers.
+    #[rustfmt::skip]
+    pub const ALL: [Square; 64] = [
+        Square::A8, Square::B8, Square::C8, Square::D8, Square::E8, Square::F8, Square::G8, Square::H8,
+        Square::A7, Square::B7, Square::C7, Square::D7, Square::E7, Square::F7, Square::G7, Square::H7,
+        Square::A6, Square::B6, Square::C6, Square::D6, Square::E6, Square::F6, Square::G6, Square::H6,
+        Square::A5, Square::B5, Square::C5, Square::D5, Square::E5, Square::F5, Square::G5, Square::H5,
+        Square::A4, Square::B4, Square::C4, Square::D4, Square::E4, Square::F4, Square::G4, Square::H4,
+        Square::A3, Square::B3, Square::C3, Square::D3, Square::E3, Square::F3, Square::G3, Square::H3,
+        Square::A2, Square::B2, Square::C2, Square::D2, Square::E2, Square::F2, Square::G2, Square::H2,
+        Square::A1, Square::B1, Square::C1, Square::D1, Square::E1, Square::F1, Square::G1, Square::H1,
+    ];
+
     // Converts a usize between 0 and 63 to a Square.
     pub fn from_usize(val: usize) -> Option<Self> {
-        if val <= 63 {
-            Some(unsafe { std::mem::transmute(val) })
+        Square::ALL.get(val).copied()
+    }
+
+    // Converts a Square back to a usize.
+    pub fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_file_and_rank(file: File, rank: Rank) -> Self {
+        Square::ALL[(7 - rank.to_usize()) * 8 + file.to_usize()]
+    }
+
+    pub fn file(self) -> File {
+        File::ALL[self.to_usize() % 8]
+    }
+
+    pub fn rank(self) -> Rank {
+        Rank::ALL[7 - self.to_usize() / 8]
+    }
+
+    // All 64 squares from A8 to H1, rank by rank.
+    pub fn iter() -> impl Iterator<Item = Square> {
+        Square::ALL.into_iter()
+    }
+
+    // The square 'file_delta' files to the right and 'rank_delta' ranks up from this one,
+    // as seen by WHITE, or None if that is off the board.
+    pub fn offset(self, file_delta: i32, rank_delta: i32) -> Option<Square> {
+        let file = self.file().to_usize() as i32 + file_delta;
+        let rank = self.rank().to_usize() as i32 + rank_delta;
+        if (0..8).contains(&file) && (0..8).contains(&rank) {
+            Some(Square::from_file_and_rank(
+                File::ALL[file as usize],
+                Rank::ALL[rank as usize],
+            ))
         } else {
             None
         }
     }
 
-    // Converts a Square back to a usize.
+    // The number of king moves between the two squares.
+    pub fn distance(self, other: Square) -> usize {
+        let (files, ranks) = self.file_and_rank_distance(other);
+        files.max(ranks)
+    }
+
+    // The number of rook moves on an empty board, counting a move along a rank and a
+    // move along a file separately.
+    pub fn manhattan_distance(self, other: Square) -> usize {
+        let (files, ranks) = self.file_and_rank_distance(other);
+        files + ranks
+    }
+
+    // The same square seen from the other side of the board, e.g. E2 becomes E7.
+    pub fn flip(self) -> Square {
+        Square::ALL[self.to_usize() ^ 56]
+    }
+
+    // The same square mirrored across the middle of the board, e.g. B1 becomes G1.
+    pub fn mirror(self) -> Square {
+        Square::ALL[self.to_usize() ^ 7]
+    }
+
+    fn file_and_rank_distance(self, other: Square) -> (usize, usize) {
+        let files = self.file().to_usize().abs_diff(other.file().to_usize());
+        let ranks = self.rank().to_usize().abs_diff(other.rank().to_usize());
+        (files, ranks)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.file(), self.rank())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSquareError(pub String);
+
+impl fmt::Display for ParseSquareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not a square name", self.0)
+    }
+}
+
+impl std::error::Error for ParseSquareError {}
+
+impl FromStr for Square {
+    type Err = ParseSquareError;
+
+    // Parses lowercase square names such as "e4".
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(file), Some(rank), None) => File::from_char(file)
+                .zip(Rank::from_char(rank))
+                .map(|(file, rank)| Square::from_file_and_rank(file, rank))
+                .ok_or(ParseSquareError(name.to_string())),
+            _ => Err(ParseSquareError(name.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+#[rustfmt::skip]
+pub enum File {
+    A, B, C, D, E, F, G, H,
+}
+
+impl File {
+    #[rustfmt::skip]
+    pub const ALL: [File; 8] = [
+        File::A, File::B, File::C, File::D, File::E, File::F, File::G, File::H,
+    ];
+
+    pub fn from_usize(val: usize) -> Option<Self> {
+        File::ALL.get(val).copied()
+    }
+
+    pub fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    // Converts a lowercase letter between 'a' and 'h' to a File.
+    pub fn from_char(c: char) -> Option<Self> {
+        if c.is_ascii_lowercase() {
+            File::from_usize(c as usize - 'a' as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        (b'a' + self as u8) as char
+    }
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+// Ranks are numbered from WHITE's side, so R1 is 0 and R8 is 7.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+#[rustfmt::skip]
+pub enum Rank {
+    R1, R2, R3, R4, R5, R6, R7, R8,
+}
+
+impl Rank {
+    #[rustfmt::skip]
+    pub const ALL: [Rank; 8] = [
+        Rank::R1, Rank::R2, Rank::R3, Rank::R4, Rank::R5, Rank::R6, Rank::R7, Rank::R8,
+    ];
+
+    pub fn from_usize(val: usize) -> Option<Self> {
+        Rank::ALL.get(val).copied()
+    }
+
     pub fn to_usize(self) -> usize {
         self as usize
     }
+
+    // Converts a digit between '1' and '8' to a Rank.
+    pub fn from_char(c: char) -> Option<Self> {
+        if ('1'..='8').contains(&c) {
+            Rank::from_usize(c as usize - '1' as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        (b'1' + self as u8) as char
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
 }
 
 pub struct Sides;
@@ -188,6 +792,12 @@ pub struct State {
     pub en_passant_square: Option<Square>,
     pub half_move_counter: u64,
     pub side_to_move: usize,
+    // Starts at 1 and goes up after every black move, as in FEN
+    pub fullmove_number: u64,
+    // Half-moves played since the start of the game, so 0 in the start position
+    pub ply: u64,
+    // Kept up to date by Position. See Zobrist for how it is computed.
+    pub zobrist_key: u64,
 }
 
 impl State {
@@ -196,14 +806,23 @@ impl State {
         en_passant_square: Option<Square>,
         half_move_counter: u64,
         side_to_move: usize,
+        fullmove_number: u64,
     ) -> Self {
         Self {
             castling_rights,
             en_passant_square,
             half_move_counter,
             side_to_move,
+            fullmove_number,
+            ply: State::ply_at(fullmove_number, side_to_move),
+            zobrist_key: 0,
         }
     }
+
+    // The ply reached when 'side_to_move' is to play in move 'fullmove_number'.
+    pub fn ply_at(fullmove_number: u64, side_to_move: usize) -> u64 {
+        fullmove_number.saturating_sub(1) * 2 + side_to_move as u64
+    }
 }
 
 impl Default for State {
@@ -213,22 +832,128 @@ impl Default for State {
             en_passant_square: None,
             half_move_counter: 0,
             side_to_move: Sides::WHITE,
+            fullmove_number: 1,
+            ply: 0,
+            zobrist_key: 0,
         }
     }
 }
 
+// Everything make_move can't recompute when a move is taken back.
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
-pub struct CastlingRights(pub(crate) u8);
+pub struct Undo {
+    // State before the move was made
+    pub state: State,
+    // Type of the piece the move captured, if any
+    pub captured: Option<usize>,
+}
+
+// The castling rights of both sides, and the file of the rook each right castles with. In
+// standard chess those are the a- and h-files, but in Chess960 the rooks can start anywhere
+// on either side of the king.
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct CastlingRights {
+    pub(crate) rights: u8,
+    // Indexed by Castling::index. Rights that aren't set keep the standard files, so equal
+    // rights always compare equal.
+    rook_files: [File; 4],
+}
+
+const STANDARD_ROOK_FILES: [File; 4] = [File::H, File::A, File::H, File::A];
 
 impl CastlingRights {
     pub fn none() -> Self {
-        Self(Castling::NO_CASTLING)
+        Self::new(Castling::NO_CASTLING)
+    }
+
+    // The given rights with the rooks on the standard a- and h-files.
+    pub fn new(rights: u8) -> Self {
+        Self {
+            rights,
+            rook_files: STANDARD_ROOK_FILES,
+        }
+    }
+
+    pub fn rights(&self) -> u8 {
+        self.rights
+    }
+
+    // Whether any of the rights in 'rights' is set.
+    pub fn has(&self, rights: u8) -> bool {
+        self.rights & rights != 0
+    }
+
+    pub fn rook_file(&self, right: u8) -> File {
+        self.rook_files[Castling::index(right)]
+    }
+
+    // The square the rook castling with 'right' starts on.
+    pub fn rook_square(&self, right: u8) -> Square {
+        Square::from_file_and_rank(self.rook_file(right), Castling::back_rank(right))
+    }
+
+    // Sets 'right', castling with the rook on 'rook_file'.
+    pub fn add(&mut self, right: u8, rook_file: File) {
+        self.rights |= right;
+        self.rook_files[Castling::index(right)] = rook_file;
+    }
+
+    pub fn remove(&mut self, rights: u8) {
+        for right in Castling::ALL {
+            if rights & right != 0 {
+                self.rights &= !right;
+                self.rook_files[Castling::index(right)] =
+                    STANDARD_ROOK_FILES[Castling::index(right)];
+            }
+        }
+    }
+
+    // Rights that are lost once a piece moves from or to 'square' because their rook
+    // starts there. Rights lost by moving the king are Castling::for_side.
+    pub fn rights_lost_on(&self, square: Square) -> u8 {
+        Castling::ALL
+            .into_iter()
+            .filter(|&right| self.has(right) && self.rook_square(right) == square)
+            .fold(Castling::NO_CASTLING, |lost, right| lost | right)
+    }
+
+    // Whether every right castles with a rook on the a- or h-file.
+    pub fn is_standard(&self) -> bool {
+        self.rook_files == STANDARD_ROOK_FILES
+    }
+}
+
+// The castling rights as written in FEN, e.g. "KQkq", or "-" when there are none. Rights
+// with a rook that isn't on the a- or h-file are written as the rook's file, e.g. "Gkq".
+// Without the board this can't tell which rook is the outermost one, which is what decides
+// between a letter and a file in X-FEN, so Chess960 rights may not match the FEN field:
+// start position 0 gives "KFkf" here and "KQkq" in FEN. Position and FenDisplay write the
+// castling field from the board instead.
+impl fmt::Display for CastlingRights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.rights == Castling::NO_CASTLING {
+            return write!(f, "-");
+        }
+        for (right, c) in Castling::ALL.into_iter().zip(['K', 'Q', 'k', 'q']) {
+            if !self.has(right) {
+                continue;
+            }
+            let file = self.rook_file(right);
+            if file == STANDARD_ROOK_FILES[Castling::index(right)] {
+                write!(f, "{}", c)?;
+            } else if right & Castling::WHITE_CASTLING != 0 {
+                write!(f, "{}", file.to_char().to_ascii_uppercase())?;
+            } else {
+                write!(f, "{}", file.to_char())?;
+            }
+        }
+        Ok(())
     }
 }
 
 impl Default for CastlingRights {
     fn default() -> Self {
-        Self(Castling::ANY_CASTLING)
+        Self::new(Castling::ANY_CASTLING)
     }
 }
 
@@ -245,11 +970,74 @@ impl Castling {
     pub const WHITE_CASTLING: u8 = Self::WHITE_OO | Self::WHITE_OOO;
     pub const BLACK_CASTLING: u8 = Self::BLACK_OO | Self::BLACK_OOO;
     pub const ANY_CASTLING: u8 = Self::KING_SIDE | Self::QUEEN_SIDE;
+
+    // Every single right, in the order FEN writes them.
+    pub const ALL: [u8; 4] = [
+        Self::WHITE_OO,
+        Self::WHITE_OOO,
+        Self::BLACK_OO,
+        Self::BLACK_OOO,
+    ];
+
+    // The position of a single right in Castling::ALL.
+    pub fn index(right: u8) -> usize {
+        right.trailing_zeros() as usize
+    }
+
+    // The king side or queen side right of 'side'.
+    pub fn right(side: usize, king_side: bool) -> u8 {
+        match (side, king_side) {
+            (Sides::WHITE, true) => Self::WHITE_OO,
+            (Sides::WHITE, false) => Self::WHITE_OOO,
+            (_, true) => Self::BLACK_OO,
+            (_, false) => Self::BLACK_OOO,
+        }
+    }
+
+    // Both rights of 'side'.
+    pub fn for_side(side: usize) -> u8 {
+        match side {
+            Sides::WHITE => Self::WHITE_CASTLING,
+            _ => Self::BLACK_CASTLING,
+        }
+    }
+
+    pub fn side_of(right: u8) -> usize {
+        if right & Self::WHITE_CASTLING != 0 {
+            Sides::WHITE
+        } else {
+            Sides::BLACK
+        }
+    }
+
+    pub fn back_rank(right: u8) -> Rank {
+        match Castling::side_of(right) {
+            Sides::WHITE => Rank::R1,
+            _ => Rank::R8,
+        }
+    }
+
+    // Where the king and the rook end up. These are the same in Chess960 as in standard
+    // chess: the g- and f-files for king side castling, the c- and d-files otherwise.
+    pub fn destinations(right: u8) -> (Square, Square) {
+        let (king, rook) = if right & Self::KING_SIDE != 0 {
+            (File::G, File::F)
+        } else {
+            (File::C, File::D)
+        };
+        let rank = Castling::back_rank(right);
+        (
+            Square::from_file_and_rank(king, rank),
+            Square::from_file_and_rank(rook, rank),
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::move_generator::MoveGenerator;
+    use std::collections::hash_map::DefaultHasher;
 
     #[test]
     fn test_bitboard_set_bit() {
@@ -275,6 +1063,70 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_bitboard_operators() {
+        let a = BitBoard(0b1100);
+        let b = BitBoard(0b1010);
+        assert_eq!(a & b, BitBoard(0b1000));
+        assert_eq!(a | b, BitBoard(0b1110));
+        assert_eq!(a ^ b, BitBoard(0b0110));
+        assert_eq!(!BitBoard::empty(), BitBoard::full());
+        assert_eq!(a << 2, BitBoard(0b110000));
+        assert_eq!(a >> 2, BitBoard(0b11));
+
+        let mut c = a;
+        c &= b;
+        assert_eq!(c, BitBoard(0b1000));
+        c |= BitBoard(0b1);
+        assert_eq!(c, BitBoard(0b1001));
+        c ^= BitBoard(0b1000);
+        assert_eq!(c, BitBoard(0b1));
+    }
+
+    #[test]
+    fn test_bitboard_count_and_lsb() {
+        let mut board = BitBoard::empty();
+        assert_eq!(board.count(), 0);
+        assert_eq!(board.lsb(), None);
+        assert_eq!(board.pop_lsb(), None);
+
+        board.set_bit(Square::A8);
+        board.set_bit(Square::E4);
+        board.set_bit(Square::H1);
+        assert_eq!(board.count(), 3);
+        assert_eq!(board.lsb(), Some(Square::H1));
+        assert_eq!(board.pop_lsb(), Some(Square::H1));
+        assert_eq!(board.pop_lsb(), Some(Square::E4));
+        assert_eq!(board.pop_lsb(), Some(Square::A8));
+        assert!(board.is_empty());
+    }
+
+    #[test]
+    fn test_bitboard_iter() {
+        let position = Position::default();
+        let pawns: Vec<Square> = position.bb_pieces[Sides::BLACK][Pieces::PAWN]
+            .iter()
+            .collect();
+        assert_eq!(
+            pawns,
+            vec![
+                Square::H7,
+                Square::G7,
+                Square::F7,
+                Square::E7,
+                Square::D7,
+                Square::C7,
+                Square::B7,
+                Square::A7
+            ]
+        );
+        assert_eq!(position.find_occupied().into_iter().len(), 32);
+        assert_eq!(
+            BitBoard::from_square(Square::C3).iter().next(),
+            Some(Square::C3)
+        );
+    }
+
     #[test]
     fn test_find_empty() {
         let position = Position::default();
@@ -354,13 +1206,191 @@ mod tests {
     }
 
     #[test]
-    fn test_position_make_move_increments_half_move_counter() {
+    fn test_position_make_move_updates_half_move_counter() {
         let mut position = Position::default();
         assert_eq!(position.state.half_move_counter, 0);
-        position.make_move(Move::new(Pieces::PAWN, Square::A2, Square::A4));
+        position.make_move(Move::new(Pieces::KNIGHT, Square::G1, Square::F3));
         assert_eq!(position.state.half_move_counter, 1);
         position.make_move(Move::new(Pieces::KNIGHT, Square::B8, Square::C6));
         assert_eq!(position.state.half_move_counter, 2);
+        // Pawn moves reset the counter.
+        position.make_move(Move::new(Pieces::PAWN, Square::E2, Square::E4));
+        assert_eq!(position.state.half_move_counter, 0);
+        position.make_move(Move::new(Pieces::KNIGHT, Square::C6, Square::D4));
+        assert_eq!(position.state.half_move_counter, 1);
+        // So do captures.
+        position.make_move(Move::new(Pieces::KNIGHT, Square::F3, Square::D4));
+        assert_eq!(position.state.half_move_counter, 0);
+    }
+
+    #[test]
+    fn test_position_make_move_updates_move_numbers() {
+        let mut position = Position::default();
+        assert_eq!((position.state.fullmove_number, position.state.ply), (1, 0));
+        position.make_move(Move::new(Pieces::PAWN, Square::E2, Square::E4));
+        assert_eq!((position.state.fullmove_number, position.state.ply), (1, 1));
+        position.make_move(Move::new(Pieces::PAWN, Square::E7, Square::E5));
+        assert_eq!((position.state.fullmove_number, position.state.ply), (2, 2));
+        position.make_move(Move::new(Pieces::KNIGHT, Square::G1, Square::F3));
+        assert_eq!((position.state.fullmove_number, position.state.ply), (2, 3));
+
+        // Positions loaded from FEN carry on from the fullmove number they were given.
+        let mut position =
+            Position::load_position_from_fen("4k3/8/8/8/8/8/8/4K3 b - - 10 57").unwrap();
+        assert_eq!(
+            (position.state.fullmove_number, position.state.ply),
+            (57, 113)
+        );
+        let mv = Move::new(Pieces::KING, Square::E8, Square::D8);
+        position.make_move(mv);
+        assert_eq!(
+            (position.state.fullmove_number, position.state.ply),
+            (58, 114)
+        );
+        position.unmake_move(mv);
+        assert_eq!(
+            (position.state.fullmove_number, position.state.ply),
+            (57, 113)
+        );
+    }
+
+    #[test]
+    fn test_position_to_fen_after_moves() {
+        let mut position = Position::default();
+        position.make_move(Move::new(Pieces::PAWN, Square::E2, Square::E4));
+        assert_eq!(
+            position.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        position.make_move(Move::new(Pieces::KNIGHT, Square::G8, Square::F6));
+        position.make_move(Move::new(Pieces::KING, Square::E1, Square::E2));
+        assert_eq!(
+            position.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2"
+        );
+    }
+
+    #[test]
+    fn test_position_make_move_capture() {
+        let mut position = Position::load_position_from_fen(
+            "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+        )
+        .unwrap();
+        position.make_move(Move::new(Pieces::PAWN, Square::E4, Square::D5));
+        assert!(!position.bb_pieces[Sides::BLACK][Pieces::PAWN].is_bit_set(Square::D5));
+        assert!(position.bb_pieces[Sides::WHITE][Pieces::PAWN].is_bit_set(Square::D5));
+    }
+
+    #[test]
+    fn test_position_make_move_en_passant() {
+        let mut position = Position::default();
+        position.make_move(Move::new(Pieces::PAWN, Square::E2, Square::E4));
+        assert_eq!(position.state.en_passant_square, Some(Square::E3));
+        position.make_move(Move::new(Pieces::PAWN, Square::A7, Square::A6));
+        assert_eq!(position.state.en_passant_square, None);
+        position.make_move(Move::new(Pieces::PAWN, Square::E4, Square::E5));
+        position.make_move(Move::new(Pieces::PAWN, Square::D7, Square::D5));
+        assert_eq!(position.state.en_passant_square, Some(Square::D6));
+
+        position.make_move(Move::new(Pieces::PAWN, Square::E5, Square::D6));
+        assert!(position.bb_pieces[Sides::WHITE][Pieces::PAWN].is_bit_set(Square::D6));
+        assert!(!position.bb_pieces[Sides::BLACK][Pieces::PAWN].is_bit_set(Square::D5));
+        assert_eq!(position.find_occupied().count(), 31);
+    }
+
+    #[test]
+    fn test_position_make_move_promotion() {
+        let mut position =
+            Position::load_position_from_fen("3n3k/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        position.make_move(Move {
+            promotion: Some(Pieces::QUEEN),
+            ..Move::new(Pieces::PAWN, Square::E7, Square::D8)
+        });
+        assert_eq!(
+            position.bb_pieces[Sides::WHITE][Pieces::PAWN],
+            BitBoard::empty()
+        );
+        assert!(position.bb_pieces[Sides::WHITE][Pieces::QUEEN].is_bit_set(Square::D8));
+        assert_eq!(
+            position.bb_pieces[Sides::BLACK][Pieces::KNIGHT],
+            BitBoard::empty()
+        );
+    }
+
+    #[test]
+    fn test_position_make_move_castling() {
+        let mut position =
+            Position::load_position_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        position.make_move(Move::new(Pieces::KING, Square::E1, Square::G1));
+        assert!(position.bb_pieces[Sides::WHITE][Pieces::KING].is_bit_set(Square::G1));
+        assert!(position.bb_pieces[Sides::WHITE][Pieces::ROOK].is_bit_set(Square::F1));
+        assert!(!position.bb_pieces[Sides::WHITE][Pieces::ROOK].is_bit_set(Square::H1));
+        assert_eq!(
+            position.state.castling_rights,
+            CastlingRights::new(Castling::BLACK_CASTLING)
+        );
+
+        position.make_move(Move::new(Pieces::KING, Square::E8, Square::C8));
+        assert!(position.bb_pieces[Sides::BLACK][Pieces::KING].is_bit_set(Square::C8));
+        assert!(position.bb_pieces[Sides::BLACK][Pieces::ROOK].is_bit_set(Square::D8));
+        assert!(!position.bb_pieces[Sides::BLACK][Pieces::ROOK].is_bit_set(Square::A8));
+        assert_eq!(position.state.castling_rights, CastlingRights::none());
+    }
+
+    #[test]
+    fn test_position_make_move_castling_rights() {
+        let mut position =
+            Position::load_position_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        // Moving a rook loses the right on its side only.
+        position.make_move(Move::new(Pieces::ROOK, Square::A1, Square::A5));
+        assert_eq!(
+            position.state.castling_rights,
+            CastlingRights::new(Castling::WHITE_OO | Castling::BLACK_CASTLING)
+        );
+        // Capturing a rook on its home square removes the opponent's right.
+        position.make_move(Move::new(Pieces::ROOK, Square::H8, Square::H1));
+        assert_eq!(
+            position.state.castling_rights,
+            CastlingRights::new(Castling::BLACK_OOO)
+        );
+    }
+
+    #[test]
+    fn test_position_make_move_chess960_castling() {
+        // The king castles onto the square it stands on, and the rook jumps over it.
+        let mut position =
+            Position::load_position_from_fen("1r4kr/8/8/8/8/8/8/RK4R1 w GAhb - 0 1").unwrap();
+        let castle = Move {
+            castling: true,
+            ..Move::new(Pieces::KING, Square::B1, Square::C1)
+        };
+        position.make_move(castle);
+        assert_eq!(position.to_fen(), "1r4kr/8/8/8/8/8/8/2KR2R1 b kq - 1 1");
+        assert_eq!(position.state.zobrist_key, position.compute_zobrist_key());
+
+        let castle = Move {
+            castling: true,
+            ..Move::new(Pieces::KING, Square::G8, Square::G8)
+        };
+        position.make_move(castle);
+        assert_eq!(position.to_fen(), "1r3rk1/8/8/8/8/8/8/2KR2R1 w - - 2 2");
+        position.unmake_move(castle);
+        assert_eq!(position.to_fen(), "1r4kr/8/8/8/8/8/8/2KR2R1 b kq - 1 1");
+    }
+
+    #[test]
+    fn test_castling_rights_rook_files() {
+        let mut rights = CastlingRights::none();
+        rights.add(Castling::WHITE_OO, File::G);
+        rights.add(Castling::BLACK_OOO, File::A);
+        assert_eq!(rights.to_string(), "Gq");
+        assert_eq!(rights.rook_square(Castling::WHITE_OO), Square::G1);
+        assert_eq!(rights.rights_lost_on(Square::G1), Castling::WHITE_OO);
+        assert_eq!(rights.rights_lost_on(Square::H1), Castling::NO_CASTLING);
+
+        rights.remove(Castling::WHITE_OO);
+        assert_eq!(rights, CastlingRights::new(Castling::BLACK_OOO));
+        assert!(rights.is_standard());
     }
 
     #[test]
@@ -372,4 +1402,252 @@ mod tests {
         position.make_move(Move::new(Pieces::KNIGHT, Square::B8, Square::C6));
         assert_eq!(position.state.side_to_move, Sides::WHITE);
     }
+
+    #[test]
+    fn test_position_unmake_move_restores_capture() {
+        let mut position = Position::load_position_from_fen(
+            "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+        )
+        .unwrap();
+        let original = position.clone();
+        let mv = Move {
+            capture: Some(Pieces::PAWN),
+            ..Move::new(Pieces::PAWN, Square::E4, Square::D5)
+        };
+        position.make_move(mv);
+        assert!(!position.bb_pieces[Sides::BLACK][Pieces::PAWN].is_bit_set(Square::D5));
+        assert_eq!(position.history.len(), 1);
+        assert_eq!(position.history[0].captured, Some(Pieces::PAWN));
+
+        position.unmake_move(mv);
+        assert_eq!(position, original);
+    }
+
+    #[test]
+    fn test_position_equality_ignores_history() {
+        let hash = |position: &Position| {
+            let mut hasher = DefaultHasher::new();
+            position.hash(&mut hasher);
+            hasher.finish()
+        };
+        let mut played = Position::default();
+        played.make_move(Move::new(Pieces::PAWN, Square::E2, Square::E4));
+        let loaded = Position::load_position_from_fen(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        )
+        .unwrap();
+        assert_eq!(played.history.len(), 1);
+        assert_eq!(played, loaded);
+        assert_eq!(hash(&played), hash(&loaded));
+    }
+
+    #[test]
+    fn test_is_square_attacked() {
+        let position = Position::default();
+        assert!(position.is_square_attacked(Square::F3, Sides::WHITE));
+        assert!(position.is_square_attacked(Square::D2, Sides::WHITE));
+        assert!(!position.is_square_attacked(Square::E4, Sides::WHITE));
+        assert!(position.is_square_attacked(Square::A6, Sides::BLACK));
+        assert!(!position.is_square_attacked(Square::E5, Sides::BLACK));
+
+        // The rook's attack along the file stops at the first piece.
+        let position =
+            Position::load_position_from_fen("4k3/8/8/8/4p3/8/8/4R1K1 w - - 0 1").unwrap();
+        assert!(position.is_square_attacked(Square::E4, Sides::WHITE));
+        assert!(!position.is_square_attacked(Square::E5, Sides::WHITE));
+    }
+
+    #[test]
+    fn test_in_check_and_checkers() {
+        assert!(!Position::default().in_check());
+        assert_eq!(Position::default().checkers(), BitBoard::empty());
+
+        let position =
+            Position::load_position_from_fen("4r2k/8/8/8/8/5n2/3Q4/4K3 w - - 0 1").unwrap();
+        assert!(position.in_check());
+        let mut expected = BitBoard::empty();
+        expected.set_bit(Square::E8);
+        expected.set_bit(Square::F3);
+        assert_eq!(position.checkers(), expected);
+
+        let position = Position::load_position_from_fen("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1").unwrap();
+        let mut expected = BitBoard::empty();
+        expected.set_bit(Square::D2);
+        assert_eq!(position.checkers(), expected);
+    }
+
+    #[test]
+    fn test_pinned() {
+        assert_eq!(Position::default().pinned(), BitBoard::empty());
+
+        // The knight on e2 and the bishop on c3 are pinned. The knight and bishop on f1 and g1
+        // both stand between the king and the rook on h1, so neither of them is pinned.
+        let position =
+            Position::load_position_from_fen("4r2k/8/8/b7/8/2B5/4N3/4KNBr w - - 0 1").unwrap();
+        let mut expected = BitBoard::empty();
+        expected.set_bit(Square::E2);
+        expected.set_bit(Square::C3);
+        assert_eq!(position.pinned(), expected);
+
+        // Enemy pieces between the king and the slider are not pinned.
+        let position =
+            Position::load_position_from_fen("4r2k/8/8/8/4n3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(position.pinned(), BitBoard::empty());
+    }
+
+    // Plays pseudo-random games and checks at every ply that making and unmaking
+    // each legal move gives back the exact same position, and that the incremental
+    // Zobrist key matches a recomputed one.
+    #[test]
+    fn test_position_make_unmake_every_legal_move() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "1r4kr/8/8/8/8/8/8/RK4R1 w GAhb - 0 1",
+        ];
+        let mut seed: u64 = 0x2545_F491;
+        for fen in fens {
+            let mut position = Position::load_position_from_fen(fen).unwrap();
+            for _ in 0..40 {
+                let moves = MoveGenerator::generate_available_moves(&position);
+                if moves.is_empty() {
+                    break;
+                }
+                for &mv in &moves {
+                    let original = position.clone();
+                    position.make_move(mv);
+                    assert_eq!(
+                        position.state.zobrist_key,
+                        position.compute_zobrist_key(),
+                        "{} {:?}",
+                        fen,
+                        mv
+                    );
+                    position.unmake_move(mv);
+                    assert_eq!(position, original, "{} {:?}", fen, mv);
+                }
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                position.make_move(moves[(seed >> 33) as usize % moves.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_display_position() {
+        let position =
+            Position::load_position_from_fen("4k3/8/8/3pP3/8/8/8/R3K3 w Q d6 0 1").unwrap();
+        assert_eq!(
+            position.to_string(),
+            "8 . . . . k . . .\n\
+             7 . . . . . . . .\n\
+             6 . . . . . . . .\n\
+             5 . . . p P . . .\n\
+             4 . . . . . . . .\n\
+             3 . . . . . . . .\n\
+             2 . . . . . . . .\n\
+             1 R . . . K . . .\n\
+             \x20 a b c d e f g h\n\
+             \n\
+             Side to move: white\n\
+             Castling rights: Q\n\
+             En passant: d6"
+        );
+
+        let diagram = format!("{:#}", Position::default());
+        assert!(diagram.starts_with("8 \u{265C} \u{265E} \u{265D} \u{265B} \u{265A}"));
+        assert!(diagram.contains("1 \u{2656} \u{2658} \u{2657} \u{2655} \u{2654}"));
+        assert!(diagram.ends_with("Castling rights: KQkq\nEn passant: -"));
+
+        // Castling rights are written as in the FEN.
+        let position = Position::load_position_from_fen(
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1",
+        )
+        .unwrap();
+        assert!(position.to_string().contains("Castling rights: KQkq\n"));
+    }
+
+    #[test]
+    fn test_display_bitboard() {
+        let bitboard = BitBoard::from_square(Square::A8) | BitBoard::from_square(Square::H1);
+        assert_eq!(
+            bitboard.to_string(),
+            "8 X . . . . . . .\n\
+             7 . . . . . . . .\n\
+             6 . . . . . . . .\n\
+             5 . . . . . . . .\n\
+             4 . . . . . . . .\n\
+             3 . . . . . . . .\n\
+             2 . . . . . . . .\n\
+             1 . . . . . . . X\n\
+             \x20 a b c d e f g h"
+        );
+        assert_eq!(CastlingRights::none().to_string(), "-");
+        assert_eq!(CastlingRights::default().to_string(), "KQkq");
+    }
+
+    #[test]
+    fn test_square_names() {
+        for square in Square::iter() {
+            assert_eq!(square.to_string().parse::<Square>(), Ok(square));
+        }
+        assert_eq!(Square::iter().count(), 64);
+        assert_eq!(Square::E4.to_string(), "e4");
+        assert_eq!("a8".parse::<Square>(), Ok(Square::A8));
+        assert_eq!("h1".parse::<Square>(), Ok(Square::H1));
+        for name in ["", "e", "e9", "i1", "E4", "e44", "4e"] {
+            assert_eq!(
+                name.parse::<Square>(),
+                Err(ParseSquareError(name.to_string()))
+            );
+        }
+        assert_eq!(Square::from_usize(63), Some(Square::H1));
+        assert_eq!(Square::from_usize(64), None);
+    }
+
+    #[test]
+    fn test_square_files_and_ranks() {
+        assert_eq!(Square::C6.file(), File::C);
+        assert_eq!(Square::C6.rank(), Rank::R6);
+        assert_eq!(Square::from_file_and_rank(File::G, Rank::R2), Square::G2);
+        for square in Square::iter() {
+            assert_eq!(
+                Square::from_file_and_rank(square.file(), square.rank()),
+                square
+            );
+        }
+        assert_eq!(File::from_char('h'), Some(File::H));
+        assert_eq!(File::from_char('i'), None);
+        assert_eq!(Rank::from_char('1'), Some(Rank::R1));
+        assert_eq!(Rank::from_char('0'), None);
+        assert_eq!(Rank::R8.to_char(), '8');
+    }
+
+    #[test]
+    fn test_square_geometry() {
+        assert_eq!(Square::E4.offset(1, 2), Some(Square::F6));
+        assert_eq!(Square::E4.offset(-4, -3), Some(Square::A1));
+        assert_eq!(Square::H4.offset(1, 0), None);
+        assert_eq!(Square::E8.offset(0, 1), None);
+
+        assert_eq!(Square::A1.distance(Square::H8), 7);
+        assert_eq!(Square::A1.manhattan_distance(Square::H8), 14);
+        assert_eq!(Square::E4.distance(Square::F6), 2);
+        assert_eq!(Square::E4.manhattan_distance(Square::F6), 3);
+        assert_eq!(Square::D5.distance(Square::D5), 0);
+
+        assert_eq!(Square::E2.flip(), Square::E7);
+        assert_eq!(Square::B1.mirror(), Square::G1);
+        for square in Square::iter() {
+            assert_eq!(square.flip().flip(), square);
+            assert_eq!(
+                square.mirror().file().to_usize(),
+                7 - square.file().to_usize()
+            );
+        }
+    }
 }
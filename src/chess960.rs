@@ -0,0 +1,133 @@
+use crate::board::{Pieces, Position};
+use crate::fen_parser::{FenParser, PIECE_CHARS};
+
+// The knight squares among the five files left after the bishops and the queen are placed,
+// for each value of the knight digit of a start position number.
+const KNIGHT_PLACEMENTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+// Start positions of Chess960 (Fischer Random Chess).
+pub struct Chess960;
+
+impl Chess960 {
+    // The number of different start positions.
+    pub const COUNT: u16 = 960;
+    // The number of the standard chess start position.
+    pub const STANDARD: u16 = 518;
+
+    // The pieces on WHITE's back rank from the a-file to the h-file in start position
+    // 'index', numbered as by Scharnagl: the digits of 'index' in a mixed radix place the
+    // light-squared bishop, the dark-squared bishop, the queen and then the knights, and
+    // the king goes between the two rooks on the remaining files.
+    pub fn back_rank(index: u16) -> Option<[usize; 8]> {
+        if index >= Chess960::COUNT {
+            return None;
+        }
+        let mut index = index as usize;
+        let mut files: [Option<usize>; 8] = [None; 8];
+
+        files[index % 4 * 2 + 1] = Some(Pieces::BISHOP);
+        index /= 4;
+        files[index % 4 * 2] = Some(Pieces::BISHOP);
+        index /= 4;
+
+        let empty = |files: &[Option<usize>; 8]| -> Vec<usize> {
+            (0..8).filter(|&file| files[file].is_none()).collect()
+        };
+        files[empty(&files)[index % 6]] = Some(Pieces::QUEEN);
+        index /= 6;
+
+        let (first, second) = KNIGHT_PLACEMENTS[index];
+        let free = empty(&files);
+        files[free[first]] = Some(Pieces::KNIGHT);
+        files[free[second]] = Some(Pieces::KNIGHT);
+
+        for (file, piece) in
+            empty(&files)
+                .into_iter()
+                .zip([Pieces::ROOK, Pieces::KING, Pieces::ROOK])
+        {
+            files[file] = Some(piece);
+        }
+        Some(files.map(|piece| piece.unwrap()))
+    }
+
+    // Start position 'index' with both sides free to castle with either rook.
+    pub fn start_position(index: u16) -> Option<Position> {
+        let back_rank = Chess960::back_rank(index)?;
+        let rank = |side: usize| -> String {
+            back_rank
+                .iter()
+                .map(|&piece| PIECE_CHARS[side][piece])
+                .collect()
+        };
+        let fen = format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+            rank(1),
+            rank(0)
+        );
+        Some(
+            FenParser::new()
+                .parse_fen(&fen)
+                .expect("start positions are valid FEN"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Castling, File};
+    use crate::fen_parser::FenDisplay;
+
+    #[test]
+    fn test_back_ranks() {
+        let names = |index: u16| -> String {
+            Chess960::back_rank(index)
+                .unwrap()
+                .iter()
+                .map(|&piece| PIECE_CHARS[0][piece])
+                .collect()
+        };
+        assert_eq!(names(0), "BBQNNRKR");
+        assert_eq!(names(Chess960::STANDARD), "RNBQKBNR");
+        assert_eq!(names(959), "RKRNNQBB");
+        assert_eq!(Chess960::back_rank(Chess960::COUNT), None);
+    }
+
+    #[test]
+    fn test_start_positions() {
+        assert_eq!(
+            Chess960::start_position(Chess960::STANDARD),
+            Some(Position::default())
+        );
+
+        let position = Chess960::start_position(0).unwrap();
+        assert_eq!(
+            position.to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert_eq!(
+            format!("{:#}", FenDisplay(&position)),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1"
+        );
+        let rights = &position.state.castling_rights;
+        assert_eq!(rights.rook_file(Castling::WHITE_OO), File::H);
+        assert_eq!(rights.rook_file(Castling::BLACK_OOO), File::F);
+
+        for index in 0..Chess960::COUNT {
+            let position = Chess960::start_position(index).unwrap();
+            assert!(position.validate().is_empty(), "{}", index);
+        }
+    }
+}
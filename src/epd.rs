@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::board::Position;
+use crate::board::State;
+use crate::fen_parser::FenError;
+use crate::fen_parser::FenParser;
+use crate::move_generator::Move;
+use crate::san::San;
+use crate::san::SanError;
+use crate::uci::Uci;
+
+pub struct EpdParser {}
+
+// The opcodes with a known meaning. Anything else is kept as Other with its raw operands.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    // bm, the best moves.
+    BestMove,
+    // am, moves to avoid.
+    AvoidMove,
+    // id, the name of the position within its suite.
+    Id,
+    // c0 to c9, comments.
+    Comment(u8),
+    // D1, D2 and so on, the perft count at that depth.
+    Depth(u8),
+    // hmvc, the halfmove clock. Also applied to the position.
+    HalfmoveClock,
+    // fmvn, the fullmove number. Also applied to the position.
+    FullmoveNumber,
+    Other(String),
+}
+
+impl Opcode {
+    fn from_name(name: &str) -> Self {
+        let numbered = |prefix: &str| {
+            name.strip_prefix(prefix)
+                .filter(|digits| !digits.is_empty() && !digits.starts_with('0'))
+                .and_then(|digits| digits.parse().ok())
+        };
+        match name {
+            "bm" => Opcode::BestMove,
+            "am" => Opcode::AvoidMove,
+            "id" => Opcode::Id,
+            "hmvc" => Opcode::HalfmoveClock,
+            "fmvn" => Opcode::FullmoveNumber,
+            _ if name.len() == 2
+                && name.starts_with('c')
+                && name.as_bytes()[1].is_ascii_digit() =>
+            {
+                Opcode::Comment(name.as_bytes()[1] - b'0')
+            }
+            _ => match numbered("D") {
+                Some(depth) => Opcode::Depth(depth),
+                None => Opcode::Other(name.to_string()),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Opcode::BestMove => write!(f, "bm"),
+            Opcode::AvoidMove => write!(f, "am"),
+            Opcode::Id => write!(f, "id"),
+            Opcode::Comment(index) => write!(f, "c{}", index),
+            Opcode::Depth(depth) => write!(f, "D{}", depth),
+            Opcode::HalfmoveClock => write!(f, "hmvc"),
+            Opcode::FullmoveNumber => write!(f, "fmvn"),
+            Opcode::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    // Moves resolved against the position, for bm and am.
+    Moves(Vec<Move>),
+    // The string of id and the comments.
+    Text(String),
+    // Perft counts, the halfmove clock and the fullmove number.
+    Number(u64),
+    // The operands of opcodes without a known meaning, with quotes removed.
+    Raw(Vec<String>),
+}
+
+// A position from an EPD line together with its operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Epd {
+    pub position: Position,
+    pub operations: HashMap<Opcode, Operand>,
+}
+
+impl Epd {
+    pub fn best_moves(&self) -> &[Move] {
+        self.moves(&Opcode::BestMove)
+    }
+
+    pub fn avoid_moves(&self) -> &[Move] {
+        self.moves(&Opcode::AvoidMove)
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        match self.operations.get(&Opcode::Id) {
+            Some(Operand::Text(id)) => Some(id),
+            _ => None,
+        }
+    }
+
+    // The expected perft count at 'depth', from the D1..Dn opcodes.
+    pub fn perft(&self, depth: u8) -> Option<u64> {
+        match self.operations.get(&Opcode::Depth(depth)) {
+            Some(Operand::Number(nodes)) => Some(*nodes),
+            _ => None,
+        }
+    }
+
+    fn moves(&self, opcode: &Opcode) -> &[Move] {
+        match self.operations.get(opcode) {
+            Some(Operand::Moves(moves)) => moves,
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdError {
+    // The four position fields are not valid.
+    Fen(FenError),
+    // An opcode that doesn't start with a letter or has characters other than letters,
+    // digits and underscores.
+    InvalidOpcode(String),
+    // A quoted operand without its closing quote.
+    UnterminatedString(Opcode),
+    // An opcode that needs operands has none, or a single operand opcode has more than one.
+    WrongOperandCount(Opcode),
+    InvalidNumber(Opcode, String),
+    InvalidMove(Opcode, SanError),
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpdError::Fen(error) => write!(f, "{}", error),
+            EpdError::InvalidOpcode(name) => write!(f, "invalid EPD opcode '{}'", name),
+            EpdError::UnterminatedString(opcode) => {
+                write!(f, "string operand of '{}' is never closed", opcode)
+            }
+            EpdError::WrongOperandCount(opcode) => {
+                write!(f, "wrong number of operands for '{}'", opcode)
+            }
+            EpdError::InvalidNumber(opcode, operand) => {
+                write!(f, "'{}' is not a valid number for '{}'", operand, opcode)
+            }
+            EpdError::InvalidMove(opcode, error) => write!(f, "{} in '{}'", error, opcode),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+impl EpdParser {
+    // Parses an EPD line: the first four FEN fields followed by operations, each an opcode,
+    // its operands and a semicolon, e.g. `... w - - bm Qg6; id "WAC.001";`. Lines that still
+    // have the halfmove clock and fullmove number of a full FEN read them as hmvc and fmvn.
+    pub fn parse_epd(&self, line: &str) -> Result<Epd, EpdError> {
+        let (fen, rest) = EpdParser::split_position(line);
+        let (counters, operations) = EpdParser::split_counters(rest);
+        let mut position = FenParser::new().parse_fen(fen).map_err(EpdError::Fen)?;
+
+        let counters = [Opcode::HalfmoveClock, Opcode::FullmoveNumber]
+            .iter()
+            .zip(counters)
+            .map(|(opcode, counter)| (opcode.to_string(), vec![counter.to_string()]));
+        let mut parsed = HashMap::new();
+        for (name, operands) in counters.chain(EpdParser::split_operations(operations)?) {
+            let opcode = Opcode::from_name(&name);
+            let operand = EpdParser::parse_operand(&position, &opcode, operands)?;
+            match (&opcode, &operand) {
+                (Opcode::HalfmoveClock, Operand::Number(clock)) => {
+                    position.state.half_move_counter = *clock;
+                }
+                (Opcode::FullmoveNumber, &Operand::Number(number)) if number > 0 => {
+                    position.state.fullmove_number = number;
+                    position.state.ply = State::ply_at(number, position.state.side_to_move);
+                }
+                _ => {}
+            }
+            parsed.insert(opcode, operand);
+        }
+
+        Ok(Epd {
+            position,
+            operations: parsed,
+        })
+    }
+
+    pub fn new() -> EpdParser {
+        EpdParser {}
+    }
+
+    // Splits 'line' after the fourth field, the last one that describes the position.
+    fn split_position(line: &str) -> (&str, &str) {
+        let mut fields = 0;
+        let mut in_field = false;
+        for (byte, c) in line.char_indices() {
+            if c.is_whitespace() && in_field {
+                fields += 1;
+                if fields == 4 {
+                    return line.split_at(byte);
+                }
+            }
+            in_field = !c.is_whitespace();
+        }
+        (line, "")
+    }
+
+    // Takes up to two numbers off the start of 'text', the FEN counters some EPD lines keep.
+    // Opcodes start with a letter, so they can't be taken for one.
+    fn split_counters(text: &str) -> (Vec<&str>, &str) {
+        let mut counters = Vec::new();
+        let mut rest = text;
+        while counters.len() < 2 {
+            let trimmed = rest.trim_start();
+            let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+            let word = &trimmed[..end];
+            if word.is_empty() || !word.bytes().all(|b| b.is_ascii_digit()) {
+                break;
+            }
+            counters.push(word);
+            rest = &trimmed[end..];
+        }
+        (counters, rest)
+    }
+
+    // Splits the operations into opcodes and their operands. Quoted operands may contain
+    // whitespace and semicolons. Empty operations are skipped, and the final semicolon
+    // is optional.
+    fn split_operations(text: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+        let mut operations = Vec::new();
+        // The words of the current operation, each flagged with whether it was quoted.
+        let mut words: Vec<(String, bool)> = Vec::new();
+        let mut chars = text.chars().peekable();
+        loop {
+            match chars.next() {
+                Some(c) if c.is_whitespace() => {}
+                Some(';') | None => {
+                    if !words.is_empty() {
+                        let (name, quoted) = words.remove(0);
+                        let valid = !quoted
+                            && name.starts_with(|c: char| c.is_ascii_alphabetic())
+                            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                        if !valid {
+                            return Err(EpdError::InvalidOpcode(name));
+                        }
+                        operations.push((name, words.drain(..).map(|(word, _)| word).collect()));
+                    }
+                    if chars.peek().is_none() {
+                        return Ok(operations);
+                    }
+                }
+                Some('"') => {
+                    let mut word = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some(c) => word.push(c),
+                            None => {
+                                let name = words.first().map_or("", |(name, _)| name.as_str());
+                                return Err(EpdError::UnterminatedString(Opcode::from_name(name)));
+                            }
+                        }
+                    }
+                    words.push((word, true));
+                }
+                Some(c) => {
+                    let mut word = c.to_string();
+                    while let Some(&next) = chars.peek() {
+                        if next.is_whitespace() || next == ';' || next == '"' {
+                            break;
+                        }
+                        word.push(next);
+                        chars.next();
+                    }
+                    words.push((word, false));
+                }
+            }
+        }
+    }
+
+    fn parse_operand(
+        position: &Position,
+        opcode: &Opcode,
+        mut words: Vec<String>,
+    ) -> Result<Operand, EpdError> {
+        match opcode {
+            Opcode::BestMove | Opcode::AvoidMove => {
+                if words.is_empty() {
+                    return Err(EpdError::WrongOperandCount(opcode.clone()));
+                }
+                let moves = words
+                    .iter()
+                    .map(|word| EpdParser::parse_move(position, word))
+                    .collect::<Result<Vec<Move>, SanError>>()
+                    .map_err(|error| EpdError::InvalidMove(opcode.clone(), error))?;
+                Ok(Operand::Moves(moves))
+            }
+            // The standard asks for a single string, but unquoted words are common too.
+            Opcode::Id | Opcode::Comment(_) => Ok(Operand::Text(words.join(" "))),
+            Opcode::Depth(_) | Opcode::HalfmoveClock | Opcode::FullmoveNumber => {
+                if words.len() != 1 {
+                    return Err(EpdError::WrongOperandCount(opcode.clone()));
+                }
+                let word = words.remove(0);
+                match word.parse() {
+                    Ok(number) => Ok(Operand::Number(number)),
+                    Err(_) => Err(EpdError::InvalidNumber(opcode.clone(), word)),
+                }
+            }
+            Opcode::Other(_) => Ok(Operand::Raw(words)),
+        }
+    }
+
+    // Move operands are SAN, but some suites use UCI notation instead.
+    fn parse_move(position: &Position, word: &str) -> Result<Move, SanError> {
+        San::parse(position, word).or_else(|error| Uci::parse(position, word).map_err(|_| error))
+    }
+}
+
+impl Default for EpdParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Pieces;
+    use crate::board::Square;
+    use crate::fen_parser::FenErrorKind;
+    use crate::fen_parser::FenField;
+    use crate::perft::Perft;
+
+    fn parse(line: &str) -> Result<Epd, EpdError> {
+        EpdParser::new().parse_epd(line)
+    }
+
+    #[test]
+    fn test_parse_test_suite_line() {
+        let epd = parse(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";",
+        )
+        .unwrap();
+        assert_eq!(
+            epd.position,
+            Position::load_position_from_fen(
+                "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            epd.best_moves(),
+            [Move::new(Pieces::QUEEN, Square::G3, Square::G6)]
+        );
+        assert!(epd.avoid_moves().is_empty());
+        assert_eq!(epd.id(), Some("WAC.001"));
+        assert_eq!(epd.operations.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_operands() {
+        let epd = parse(
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - bm O-O e1c1; am Kf1; c0 \"a; b\"; c1 plain text; \
+             hmvc 12; fmvn 40; xyz 1 \"two words\";",
+        )
+        .unwrap();
+        let moves: Vec<String> = epd.best_moves().iter().map(|mv| Uci::format(*mv)).collect();
+        assert_eq!(moves, ["e1g1", "e1c1"]);
+        assert!(epd.best_moves().iter().all(|mv| mv.castling));
+        assert_eq!(epd.avoid_moves().len(), 1);
+        assert_eq!(
+            epd.operations[&Opcode::Comment(0)],
+            Operand::Text("a; b".to_string())
+        );
+        assert_eq!(
+            epd.operations[&Opcode::Comment(1)],
+            Operand::Text("plain text".to_string())
+        );
+        assert_eq!(epd.operations[&Opcode::FullmoveNumber], Operand::Number(40));
+        assert_eq!(epd.position.state.half_move_counter, 12);
+        assert_eq!(epd.position.state.fullmove_number, 40);
+        assert_eq!(
+            epd.operations[&Opcode::Other("xyz".to_string())],
+            Operand::Raw(vec!["1".to_string(), "two words".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_parse_fen_counters() {
+        let epd = parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 bm e4;").unwrap();
+        assert_eq!(epd.position, Position::default());
+        assert_eq!(
+            epd.best_moves(),
+            [Move::new(Pieces::PAWN, Square::E2, Square::E4)]
+        );
+        assert_eq!(epd.operations[&Opcode::HalfmoveClock], Operand::Number(0));
+        assert_eq!(epd.operations[&Opcode::FullmoveNumber], Operand::Number(1));
+
+        let epd = parse("4k3/8/8/8/8/8/8/4K2R b K - 7 36 id \"counters\";").unwrap();
+        assert_eq!(epd.position.state.half_move_counter, 7);
+        assert_eq!(epd.position.state.fullmove_number, 36);
+        assert_eq!(epd.id(), Some("counters"));
+        assert_eq!(epd.operations.len(), 3);
+
+        // Only the halfmove clock is given.
+        let epd = parse("4k3/8/8/8/8/8/8/4K2R b K - 7").unwrap();
+        assert_eq!(epd.position.state.half_move_counter, 7);
+        assert_eq!(epd.operations.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("8/8/8/8/8/8/8/8 w -"),
+            Err(EpdError::Fen(FenError {
+                field: FenField::EnPassantSquare,
+                offset: 19,
+                kind: FenErrorKind::MissingField,
+            }))
+        );
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -";
+        assert_eq!(
+            parse(&format!("{} bm e5;", start)),
+            Err(EpdError::InvalidMove(
+                Opcode::BestMove,
+                SanError::IllegalMove("e5".to_string())
+            ))
+        );
+        assert_eq!(
+            parse(&format!("{} bm;", start)),
+            Err(EpdError::WrongOperandCount(Opcode::BestMove))
+        );
+        assert_eq!(
+            parse(&format!("{} D1 twenty;", start)),
+            Err(EpdError::InvalidNumber(
+                Opcode::Depth(1),
+                "twenty".to_string()
+            ))
+        );
+        assert_eq!(
+            parse(&format!("{} id \"unclosed;", start)),
+            Err(EpdError::UnterminatedString(Opcode::Id))
+        );
+        assert_eq!(
+            parse(&format!("{} 1x 2;", start)),
+            Err(EpdError::InvalidOpcode("1x".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_perft_suite() {
+        // Lines in the style of the standard perft EPD collections.
+        let suite = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400 ;D3 8902",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ;D1 48 ;D2 2039",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ;D1 14 ;D2 191 ;D3 2812",
+        ];
+        for line in suite {
+            let epd = parse(line).unwrap();
+            let mut position = epd.position.clone();
+            for depth in 1..=3 {
+                if let Some(expected) = epd.perft(depth) {
+                    assert_eq!(Perft::perft(&mut position, depth as u32), expected);
+                }
+            }
+        }
+    }
+}
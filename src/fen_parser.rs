@@ -1,142 +1,502 @@
-use crate::board::{BitBoard, Castling, CastlingRights, Position, Sides, Square, State};
-use fen;
-use fen::BoardState;
+use std::fmt;
 
-pub struct FenParser {}
+use crate::board::{
+    BitBoard, Castling, CastlingRights, File, Pieces, Position, Sides, Square, State,
+};
+use crate::validation::Violation;
+
+pub struct FenParser {
+    // Whether parsed positions must also pass Position::validate
+    validate: bool,
+}
+
+// Formats a Position as a FEN string, e.g. `println!("{}", FenDisplay(&position))`.
+// The castling field is X-FEN, or Shredder-FEN with the alternate flag, e.g. `{:#}`.
+pub struct FenDisplay<'a>(pub &'a Position);
+
+// FEN letters for each piece, first for WHITE and then for BLACK.
+pub(crate) const PIECE_CHARS: [[char; 6]; 2] = [
+    ['P', 'N', 'B', 'R', 'Q', 'K'],
+    ['p', 'n', 'b', 'r', 'q', 'k'],
+];
+
+// The six space separated fields of a FEN string, or the position they describe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FenField {
+    PiecePlacement,
+    SideToMove,
+    CastlingRights,
+    EnPassantSquare,
+    HalfmoveClock,
+    FullmoveNumber,
+    // The position as a whole, for problems no single field is to blame for. Errors for it
+    // have offset 0.
+    Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenErrorKind {
+    // A required field is missing.
+    MissingField,
+    // There is text after the fullmove number.
+    TooManyFields,
+    // A character that isn't allowed in this field.
+    UnexpectedCharacter(char),
+    // A rank that doesn't describe exactly eight squares.
+    WrongRankLength,
+    // The piece placement doesn't describe exactly eight ranks.
+    WrongRankCount,
+    // The en passant field is neither '-' nor a square name.
+    InvalidSquare,
+    // The halfmove clock or fullmove number isn't a valid number.
+    InvalidNumber,
+    // The FEN is well formed but describes an impossible position. Only reported by
+    // parsers created with FenParser::validating.
+    IllegalPosition(Vec<Violation>),
+}
+
+// Describes which field of a FEN string could not be parsed and the character offset
+// in the whole string where the problem was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FenError {
+    pub field: FenField,
+    pub offset: usize,
+    pub kind: FenErrorKind,
+}
+
+impl FenError {
+    fn new(field: FenField, offset: usize, kind: FenErrorKind) -> Self {
+        Self {
+            field,
+            offset,
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match &self.kind {
+            FenErrorKind::MissingField => "missing field".to_string(),
+            FenErrorKind::TooManyFields => "unexpected text after the last field".to_string(),
+            FenErrorKind::UnexpectedCharacter(c) => format!("unexpected character '{}'", c),
+            FenErrorKind::WrongRankLength => "rank doesn't have 8 squares".to_string(),
+            FenErrorKind::WrongRankCount => "board doesn't have 8 ranks".to_string(),
+            FenErrorKind::InvalidSquare => "invalid square".to_string(),
+            FenErrorKind::InvalidNumber => "invalid number".to_string(),
+            FenErrorKind::IllegalPosition(violations) => format!(
+                "illegal position: {}",
+                violations
+                    .iter()
+                    .map(|violation| violation.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        if self.field == FenField::Position {
+            return write!(f, "invalid FEN: {}", reason);
+        }
+        write!(
+            f,
+            "invalid FEN {:?} at character {}: {}",
+            self.field, self.offset, reason
+        )
+    }
+}
+
+impl std::error::Error for FenError {}
 
 impl FenParser {
-    pub fn parse_fen(&self, fen: &str) -> Position {
-        let fen_board_state = fen::BoardState::from_fen(fen).unwrap();
-        let en_passant_square = match fen_board_state.en_passant_square {
-            Some(pos) => Square::from_usize(FenParser::convert_index(pos as usize)),
-            None => None,
+    // Parses a FEN string into a Position. The halfmove clock and fullmove number may be
+    // left out, in which case they default to 0 and 1.
+    pub fn parse_fen(&self, fen: &str) -> Result<Position, FenError> {
+        let fields = FenParser::split_fields(fen);
+        let field = |index: usize, name: FenField| {
+            fields.get(index).copied().ok_or(FenError::new(
+                name,
+                fen.chars().count(),
+                FenErrorKind::MissingField,
+            ))
         };
-        let side_to_move = match fen_board_state.side_to_play {
-            fen::Color::White => Sides::WHITE,
-            fen::Color::Black => Sides::BLACK,
+
+        let (offset, placement) = field(0, FenField::PiecePlacement)?;
+        let bb_pieces = FenParser::parse_piece_placement(placement, offset)?;
+
+        let (offset, side) = field(1, FenField::SideToMove)?;
+        let side_to_move = match side {
+            "w" => Sides::WHITE,
+            "b" => Sides::BLACK,
+            _ => {
+                return Err(FenParser::unexpected_character(
+                    FenField::SideToMove,
+                    side,
+                    offset,
+                    |c| c == 'w' || c == 'b',
+                ))
+            }
         };
 
-        let state = State {
-            castling_rights: FenParser::find_castling_rights(&fen_board_state),
-            en_passant_square,
-            side_to_move,
-            half_move_counter: fen_board_state.halfmove_clock,
+        let (offset, castling) = field(2, FenField::CastlingRights)?;
+        let castling_rights = FenParser::parse_castling_rights(castling, offset, &bb_pieces)?;
+
+        let (offset, en_passant) = field(3, FenField::EnPassantSquare)?;
+        let en_passant_square = match en_passant {
+            "-" => None,
+            name => Some(name.parse::<Square>().map_err(|_| {
+                FenError::new(
+                    FenField::EnPassantSquare,
+                    offset,
+                    FenErrorKind::InvalidSquare,
+                )
+            })?),
         };
 
-        // The fen crate parser is kind of broken and parses pieces in the wrong order.
-        // This is why the colors, kings & queens are swapped when constructing the bitboards.
-        let bb_pieces = [
-            [
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::Pawn,
-                    fen::Color::Black,
-                ),
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::Knight,
-                    fen::Color::Black,
-                ),
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::Bishop,
-                    fen::Color::Black,
-                ),
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::Rook,
-                    fen::Color::Black,
-                ),
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::King,
-                    fen::Color::Black,
-                ),
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::Queen,
-                    fen::Color::Black,
-                ),
-            ],
-            [
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::Pawn,
-                    fen::Color::White,
-                ),
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::Knight,
-                    fen::Color::White,
-                ),
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::Bishop,
-                    fen::Color::White,
-                ),
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::Rook,
-                    fen::Color::White,
-                ),
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::King,
-                    fen::Color::White,
-                ),
-                FenParser::parse_to_bitboard(
-                    &fen_board_state,
-                    fen::PieceKind::Queen,
-                    fen::Color::White,
-                ),
-            ],
-        ];
+        let half_move_counter = match fields.get(4) {
+            Some(&(offset, clock)) => {
+                FenParser::parse_number(clock, offset, FenField::HalfmoveClock)?
+            }
+            None => 0,
+        };
+        let fullmove_number = match fields.get(5) {
+            Some(&(offset, number)) => {
+                let fullmove_number =
+                    FenParser::parse_number(number, offset, FenField::FullmoveNumber)?;
+                if fullmove_number == 0 {
+                    return Err(FenError::new(
+                        FenField::FullmoveNumber,
+                        offset,
+                        FenErrorKind::InvalidNumber,
+                    ));
+                }
+                fullmove_number
+            }
+            None => 1,
+        };
+        if let Some(&(offset, _)) = fields.get(6) {
+            return Err(FenError::new(
+                FenField::FullmoveNumber,
+                offset,
+                FenErrorKind::TooManyFields,
+            ));
+        }
+
+        let state = State::new(
+            castling_rights,
+            en_passant_square,
+            half_move_counter,
+            side_to_move,
+            fullmove_number,
+        );
 
-        Position::new(state, bb_pieces)
+        let position = Position::new(state, bb_pieces);
+        if self.validate {
+            let violations = position.validate();
+            if !violations.is_empty() {
+                return Err(FenError::new(
+                    FenField::Position,
+                    0,
+                    FenErrorKind::IllegalPosition(violations),
+                ));
+            }
+        }
+        Ok(position)
     }
 
     pub fn new() -> FenParser {
-        FenParser {}
+        FenParser { validate: false }
     }
 
-    // Converts indices from the fen crate to indices used in the bitboard crate.
-    fn convert_index(idx: usize) -> usize {
-        (7 - idx / 8) * 8 + (idx % 8)
+    // A parser that also rejects positions that can't occur in a legal game, so they never
+    // reach the move generator.
+    pub fn validating() -> FenParser {
+        FenParser { validate: true }
+    }
+
+    // Splits 'fen' on whitespace, keeping the character offset where each field starts.
+    fn split_fields(fen: &str) -> Vec<(usize, &str)> {
+        let mut fields = Vec::new();
+        let mut start: Option<(usize, usize)> = None;
+        for (offset, (byte, c)) in fen.char_indices().enumerate() {
+            match (c.is_whitespace(), start) {
+                (true, Some((field_offset, field_byte))) => {
+                    fields.push((field_offset, &fen[field_byte..byte]));
+                    start = None;
+                }
+                (false, None) => start = Some((offset, byte)),
+                _ => {}
+            }
+        }
+        if let Some((field_offset, field_byte)) = start {
+            fields.push((field_offset, &fen[field_byte..]));
+        }
+        fields
     }
 
-    fn find_castling_rights(board: &BoardState) -> CastlingRights {
+    fn parse_piece_placement(
+        placement: &str,
+        offset: usize,
+    ) -> Result<[[BitBoard; 6]; 2], FenError> {
+        let error = |offset: usize, kind: FenErrorKind| {
+            FenError::new(FenField::PiecePlacement, offset, kind)
+        };
+        let mut bb_pieces = [[BitBoard::empty(); 6]; 2];
+        // Ranks are listed from the 8th to the 1st, which is the order of the Square enum.
+        let mut row = 0;
+        let mut file = 0;
+
+        for (index, c) in placement.chars().enumerate() {
+            let char_offset = offset + index;
+            match c {
+                '/' => {
+                    if file != 8 {
+                        return Err(error(char_offset, FenErrorKind::WrongRankLength));
+                    }
+                    row += 1;
+                    file = 0;
+                    if row == 8 {
+                        return Err(error(char_offset, FenErrorKind::WrongRankCount));
+                    }
+                }
+                '1'..='8' => {
+                    file += c as usize - '0' as usize;
+                    if file > 8 {
+                        return Err(error(char_offset, FenErrorKind::WrongRankLength));
+                    }
+                }
+                _ => {
+                    let (side, piece) = FenParser::parse_piece(c)
+                        .ok_or(error(char_offset, FenErrorKind::UnexpectedCharacter(c)))?;
+                    if file == 8 {
+                        return Err(error(char_offset, FenErrorKind::WrongRankLength));
+                    }
+                    let square = Square::from_usize(row * 8 + file).unwrap();
+                    bb_pieces[side][piece].set_bit(square);
+                    file += 1;
+                }
+            }
+        }
+
+        let end = offset + placement.chars().count();
+        if file != 8 {
+            return Err(error(end, FenErrorKind::WrongRankLength));
+        }
+        if row != 7 {
+            return Err(error(end, FenErrorKind::WrongRankCount));
+        }
+        Ok(bb_pieces)
+    }
+
+    fn parse_piece(c: char) -> Option<(usize, usize)> {
+        let side = if c.is_ascii_uppercase() {
+            Sides::WHITE
+        } else {
+            Sides::BLACK
+        };
+        let piece = match c.to_ascii_lowercase() {
+            'p' => Pieces::PAWN,
+            'n' => Pieces::KNIGHT,
+            'b' => Pieces::BISHOP,
+            'r' => Pieces::ROOK,
+            'q' => Pieces::QUEEN,
+            'k' => Pieces::KING,
+            _ => return None,
+        };
+        Some((side, piece))
+    }
+
+    // Reads standard "KQkq" letters as well as the rook files of Shredder-FEN and X-FEN,
+    // e.g. "HAha" or "Gkq". As in X-FEN, 'K' and 'Q' castle with the outermost rook on
+    // that side of the king, and a file letter with the rook on that file.
+    fn parse_castling_rights(
+        castling: &str,
+        offset: usize,
+        bb_pieces: &[[BitBoard; 6]; 2],
+    ) -> Result<CastlingRights, FenError> {
         let mut castling_rights = CastlingRights::none();
-        if board.white_can_oo {
-            castling_rights.0 |= Castling::WHITE_OO;
+        if castling == "-" {
+            return Ok(castling_rights);
         }
-        if board.white_can_ooo {
-            castling_rights.0 |= Castling::WHITE_OOO
+        for (index, c) in castling.chars().enumerate() {
+            let side = if c.is_ascii_uppercase() {
+                Sides::WHITE
+            } else {
+                Sides::BLACK
+            };
+            let right = match c.to_ascii_lowercase() {
+                'k' => Some((true, None)),
+                'q' => Some((false, None)),
+                letter => File::from_char(letter).map(|file| {
+                    let king_side = file > FenParser::king_file(bb_pieces, side);
+                    (king_side, Some(file))
+                }),
+            }
+            .map(|(king_side, file)| {
+                let right = Castling::right(side, king_side);
+                let file = file.unwrap_or(FenParser::castling_rook_file(bb_pieces, right));
+                (right, file)
+            });
+            // Unknown characters and repeated rights are both rejected.
+            let (right, file) = match right {
+                Some((right, file)) if !castling_rights.has(right) => (right, file),
+                _ => {
+                    return Err(FenError::new(
+                        FenField::CastlingRights,
+                        offset + index,
+                        FenErrorKind::UnexpectedCharacter(c),
+                    ))
+                }
+            };
+            castling_rights.add(right, file);
         }
-        if board.black_can_oo {
-            castling_rights.0 |= Castling::BLACK_OO;
+        Ok(castling_rights)
+    }
+
+    // The file of the king of 'side' if it's on its back rank, or the e-file otherwise.
+    fn king_file(bb_pieces: &[[BitBoard; 6]; 2], side: usize) -> File {
+        let rank = Castling::back_rank(Castling::for_side(side));
+        bb_pieces[side][Pieces::KING]
+            .iter()
+            .find(|square| square.rank() == rank)
+            .map_or(File::E, |square| square.file())
+    }
+
+    // The file 'K' or 'Q' stands for: the outermost rook on that side of the king, or the
+    // standard h- or a-file when there is no rook there.
+    fn castling_rook_file(bb_pieces: &[[BitBoard; 6]; 2], right: u8) -> File {
+        let side = Castling::side_of(right);
+        let king_file = FenParser::king_file(bb_pieces, side);
+        let rank = Castling::back_rank(right);
+        let rook_files = bb_pieces[side][Pieces::ROOK]
+            .iter()
+            .filter(|square| square.rank() == rank)
+            .map(|square| square.file());
+        if right & Castling::KING_SIDE != 0 {
+            rook_files
+                .filter(|&file| file > king_file)
+                .max()
+                .unwrap_or(File::H)
+        } else {
+            rook_files
+                .filter(|&file| file < king_file)
+                .min()
+                .unwrap_or(File::A)
         }
-        if board.black_can_ooo {
-            castling_rights.0 |= Castling::BLACK_OOO;
+    }
+
+    fn parse_number(number: &str, offset: usize, field: FenField) -> Result<u64, FenError> {
+        if let Some(index) = number.chars().position(|c| !c.is_ascii_digit()) {
+            let c = number.chars().nth(index).unwrap();
+            return Err(FenError::new(
+                field,
+                offset + index,
+                FenErrorKind::UnexpectedCharacter(c),
+            ));
         }
-        castling_rights
+        number
+            .parse()
+            .map_err(|_| FenError::new(field, offset, FenErrorKind::InvalidNumber))
     }
 
-    fn parse_to_bitboard(
-        fen_board_state: &BoardState,
-        piece_kind: fen::PieceKind,
-        piece_color: fen::Color,
-    ) -> BitBoard {
-        let mut bitboard = BitBoard(0);
-
-        for (idx, piece_option) in fen_board_state.pieces.iter().enumerate() {
-            if let Some(piece) = piece_option {
-                if piece.kind == piece_kind && piece.color == piece_color {
-                    bitboard.0 |= 1 << FenParser::convert_index(idx);
+    // Reports the first character of 'text' that 'is_allowed' rejects.
+    fn unexpected_character(
+        field: FenField,
+        text: &str,
+        offset: usize,
+        is_allowed: impl Fn(char) -> bool,
+    ) -> FenError {
+        let (index, c) = text
+            .chars()
+            .enumerate()
+            .find(|&(index, c)| index > 0 || !is_allowed(c))
+            .unwrap();
+        FenError::new(field, offset + index, FenErrorKind::UnexpectedCharacter(c))
+    }
+}
+
+impl fmt::Display for FenDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let position = self.0;
+        for row in 0..8 {
+            let mut empty = 0;
+            for file in 0..8 {
+                let square = Square::from_usize(row * 8 + file).unwrap();
+                let piece = (0..2).find_map(|side| {
+                    position
+                        .find_piece_on(side, square)
+                        .map(|piece| PIECE_CHARS[side][piece])
+                });
+                match piece {
+                    Some(c) => {
+                        if empty > 0 {
+                            write!(f, "{}", empty)?;
+                            empty = 0;
+                        }
+                        write!(f, "{}", c)?;
+                    }
+                    None => empty += 1,
                 }
             }
+            if empty > 0 {
+                write!(f, "{}", empty)?;
+            }
+            if row < 7 {
+                write!(f, "/")?;
+            }
         }
 
-        bitboard
+        let state = &position.state;
+        let side = if state.side_to_move == Sides::WHITE {
+            "w"
+        } else {
+            "b"
+        };
+        write!(f, " {} ", side)?;
+
+        FenDisplay::write_castling_rights(f, position, f.alternate())?;
+
+        match state.en_passant_square {
+            Some(square) => write!(f, " {}", square)?,
+            None => write!(f, " -")?,
+        }
+
+        write!(f, " {} {}", state.half_move_counter, state.fullmove_number)
+    }
+}
+
+impl FenDisplay<'_> {
+    // Writes the castling field as X-FEN, which is plain "KQkq" for standard chess, or as
+    // Shredder-FEN, e.g. "HAha", if 'shredder' is set.
+    pub(crate) fn write_castling_rights(
+        f: &mut fmt::Formatter,
+        position: &Position,
+        shredder: bool,
+    ) -> fmt::Result {
+        let castling_rights = &position.state.castling_rights;
+        if !castling_rights.has(Castling::ANY_CASTLING) {
+            return write!(f, "-");
+        }
+        for (right, letter) in Castling::ALL.into_iter().zip(['K', 'Q', 'k', 'q']) {
+            if !castling_rights.has(right) {
+                continue;
+            }
+            let file = castling_rights.rook_file(right);
+            let outermost = file == FenParser::castling_rook_file(&position.bb_pieces, right);
+            let c = if outermost && !shredder {
+                letter
+            } else if Castling::side_of(right) == Sides::WHITE {
+                file.to_char().to_ascii_uppercase()
+            } else {
+                file.to_char()
+            };
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for FenParser {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -147,10 +507,10 @@ mod tests {
     fn test_parse_fen_any_castling() {
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
         let fen_parser = FenParser::new();
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(
             position.state.castling_rights,
-            CastlingRights(Castling::ANY_CASTLING)
+            CastlingRights::new(Castling::ANY_CASTLING)
         );
     }
 
@@ -159,26 +519,30 @@ mod tests {
         let fen_parser = FenParser::new();
         // White can castle king-side, but not queen-side. Black can't castle.
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w K - 0 1";
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(
             position.state.castling_rights,
-            CastlingRights(Castling::WHITE_OO & !Castling::WHITE_OOO & !Castling::BLACK_CASTLING)
+            CastlingRights::new(
+                Castling::WHITE_OO & !Castling::WHITE_OOO & !Castling::BLACK_CASTLING
+            )
         );
 
         // White can castle queen-side, but not king-side. Black can't castle.
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Q - 0 1";
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(
             position.state.castling_rights,
-            CastlingRights(Castling::WHITE_OOO & !Castling::WHITE_OO & !Castling::BLACK_CASTLING)
+            CastlingRights::new(
+                Castling::WHITE_OOO & !Castling::WHITE_OO & !Castling::BLACK_CASTLING
+            )
         );
 
         // White can castle both sides. Black can't castle.
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1";
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(
             position.state.castling_rights,
-            CastlingRights(Castling::WHITE_CASTLING & !Castling::BLACK_CASTLING)
+            CastlingRights::new(Castling::WHITE_CASTLING & !Castling::BLACK_CASTLING)
         );
     }
 
@@ -187,26 +551,30 @@ mod tests {
         let fen_parser = FenParser::new();
         // Black can castle king-side, but not queen-side. White can't castle.
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b k - 0 1";
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(
             position.state.castling_rights,
-            CastlingRights(Castling::BLACK_OO & !Castling::BLACK_OOO & !Castling::WHITE_CASTLING)
+            CastlingRights::new(
+                Castling::BLACK_OO & !Castling::BLACK_OOO & !Castling::WHITE_CASTLING
+            )
         );
 
         // Black can castle queen-side, but not king-side. White can't castle.
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b q - 0 1";
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(
             position.state.castling_rights,
-            CastlingRights(Castling::BLACK_OOO & !Castling::BLACK_OO & !Castling::WHITE_CASTLING)
+            CastlingRights::new(
+                Castling::BLACK_OOO & !Castling::BLACK_OO & !Castling::WHITE_CASTLING
+            )
         );
 
         // Black can castle both sides. White can't castle.
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b kq - 0 1";
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(
             position.state.castling_rights,
-            CastlingRights(Castling::BLACK_CASTLING & !Castling::WHITE_CASTLING)
+            CastlingRights::new(Castling::BLACK_CASTLING & !Castling::WHITE_CASTLING)
         );
     }
 
@@ -215,10 +583,10 @@ mod tests {
         let fen_parser = FenParser::new();
         // Neither side can castle.
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1";
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(
             position.state.castling_rights,
-            CastlingRights(Castling::NO_CASTLING)
+            CastlingRights::new(Castling::NO_CASTLING)
         );
     }
 
@@ -226,31 +594,19 @@ mod tests {
     fn test_parse_fen_en_passant() {
         let fen_parser = FenParser::new();
         let fen = "rnbqkbnr/pppp1ppp/8/4P3/8/8/PPPP1PPP/RNBQKBNR b KQkq a1 0 1";
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(position.state.en_passant_square, Some(Square::A1));
 
         let fen = "rnbqkbnr/pppp1ppp/8/4P3/8/8/PPPP1PPP/RNBQKBNR b KQkq h8 0 1";
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(position.state.en_passant_square, Some(Square::H8));
     }
 
-    #[test]
-    fn test_convert_index() {
-        assert_eq!(Square::A1 as usize, FenParser::convert_index(0));
-        assert_eq!(Square::A8 as usize, 0);
-        assert_eq!(Square::B1 as usize, FenParser::convert_index(1));
-        assert_eq!(Square::B8 as usize, 1);
-        assert_eq!(Square::H8 as usize, FenParser::convert_index(63));
-        assert_eq!(Square::H1 as usize, 63);
-        assert_eq!(Square::E1 as usize, FenParser::convert_index(4));
-        assert_eq!(Square::E1 as usize, 60);
-    }
-
     #[test]
     fn test_parse_position_pieces_from_fen() {
         let fen_parser = FenParser::new();
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(Position::default().bb_pieces, position.bb_pieces);
     }
 
@@ -258,9 +614,244 @@ mod tests {
     fn test_parse_position_state_from_fen() {
         let fen_parser = FenParser::new();
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        let position = fen_parser.parse_fen(fen);
+        let position = fen_parser.parse_fen(fen).unwrap();
         assert_eq!(Position::default().state, position.state);
     }
 
-    // TODO: Test with random game state
+    #[test]
+    fn test_parse_position_from_fen() {
+        let fen_parser = FenParser::new();
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b Kq - 12 40";
+        let position = fen_parser.parse_fen(fen).unwrap();
+        assert!(position.bb_pieces[Sides::WHITE][Pieces::KING].is_bit_set(Square::E1));
+        assert!(position.bb_pieces[Sides::WHITE][Pieces::QUEEN].is_bit_set(Square::F3));
+        assert!(position.bb_pieces[Sides::BLACK][Pieces::QUEEN].is_bit_set(Square::E7));
+        assert!(position.bb_pieces[Sides::BLACK][Pieces::PAWN].is_bit_set(Square::H3));
+        assert_eq!(position.find_occupied().count(), 32);
+        assert_eq!(position.state.side_to_move, Sides::BLACK);
+        assert_eq!(
+            position.state.castling_rights,
+            CastlingRights::new(Castling::WHITE_OO | Castling::BLACK_OOO)
+        );
+        assert_eq!(position.state.half_move_counter, 12);
+        assert_eq!(position.state.fullmove_number, 40);
+        assert_eq!(position.state.ply, 79);
+    }
+
+    #[test]
+    fn test_parse_fen_without_move_counters() {
+        let fen_parser = FenParser::new();
+        let position = fen_parser
+            .parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -")
+            .unwrap();
+        assert_eq!(position, Position::default());
+    }
+
+    #[test]
+    fn test_parse_fen_errors() {
+        let fen_parser = FenParser::new();
+        let cases = [
+            ("", FenField::PiecePlacement, 0, FenErrorKind::MissingField),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+                FenField::SideToMove,
+                43,
+                FenErrorKind::MissingField,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBXKBNR w KQkq - 0 1",
+                FenField::PiecePlacement,
+                38,
+                FenErrorKind::UnexpectedCharacter('X'),
+            ),
+            (
+                "rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenField::PiecePlacement,
+                16,
+                FenErrorKind::WrongRankLength,
+            ),
+            (
+                "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenField::PiecePlacement,
+                18,
+                FenErrorKind::UnexpectedCharacter('9'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/44p/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenField::PiecePlacement,
+                20,
+                FenErrorKind::WrongRankLength,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenField::PiecePlacement,
+                41,
+                FenErrorKind::WrongRankCount,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+                FenField::SideToMove,
+                44,
+                FenErrorKind::UnexpectedCharacter('x'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkk - 0 1",
+                FenField::CastlingRights,
+                49,
+                FenErrorKind::UnexpectedCharacter('k'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+                FenField::EnPassantSquare,
+                51,
+                FenErrorKind::InvalidSquare,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+                FenField::HalfmoveClock,
+                53,
+                FenErrorKind::UnexpectedCharacter('x'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0",
+                FenField::FullmoveNumber,
+                55,
+                FenErrorKind::InvalidNumber,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 extra",
+                FenField::FullmoveNumber,
+                57,
+                FenErrorKind::TooManyFields,
+            ),
+        ];
+        for (fen, field, offset, kind) in cases {
+            assert_eq!(
+                fen_parser.parse_fen(fen),
+                Err(FenError::new(field, offset, kind)),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_fen_display_start_position() {
+        assert_eq!(
+            FenDisplay(&Position::default()).to_string(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_fen_round_trip() {
+        let fen_parser = FenParser::new();
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R b KQkq - 1 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "4k3/8/8/8/8/8/8/4K2R w K - 99 1",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "8/8/4k3/8/8/4K3/8/8 b - - 37 112",
+        ];
+        for fen in fens {
+            let position = fen_parser.parse_fen(fen).unwrap();
+            assert_eq!(FenDisplay(&position).to_string(), fen);
+            assert_eq!(fen_parser.parse_fen(&position.to_fen()).unwrap(), position);
+        }
+    }
+
+    #[test]
+    fn test_chess960_castling_fields() {
+        let fen_parser = FenParser::new();
+        // Shredder-FEN names every rook by its file, X-FEN only the ones that aren't the
+        // outermost rook on their side of the king.
+        let position = fen_parser
+            .parse_fen("rr2k1r1/8/8/8/8/8/8/1R2K1RR w GBgb - 0 1")
+            .unwrap();
+        let rights = &position.state.castling_rights;
+        assert_eq!(rights.rook_file(Castling::WHITE_OO), File::G);
+        assert_eq!(rights.rook_file(Castling::WHITE_OOO), File::B);
+        assert_eq!(rights.rook_file(Castling::BLACK_OO), File::G);
+        assert_eq!(rights.rook_file(Castling::BLACK_OOO), File::B);
+        assert_eq!(
+            position.to_fen(),
+            "rr2k1r1/8/8/8/8/8/8/1R2K1RR w GQkb - 0 1"
+        );
+        assert_eq!(
+            format!("{:#}", FenDisplay(&position)),
+            "rr2k1r1/8/8/8/8/8/8/1R2K1RR w GBgb - 0 1"
+        );
+        assert_eq!(fen_parser.parse_fen(&position.to_fen()).unwrap(), position);
+
+        // 'K' and 'Q' pick the outermost rook on their side of the king.
+        let position = fen_parser
+            .parse_fen("1r2kr2/8/8/8/8/8/8/1R3KR1 w KQkq - 0 1")
+            .unwrap();
+        let rights = &position.state.castling_rights;
+        assert_eq!(rights.rook_file(Castling::WHITE_OO), File::G);
+        assert_eq!(rights.rook_file(Castling::WHITE_OOO), File::B);
+        assert_eq!(rights.rook_file(Castling::BLACK_OO), File::F);
+        assert_eq!(rights.rook_file(Castling::BLACK_OOO), File::B);
+        assert_eq!(
+            format!("{:#}", FenDisplay(&position)),
+            "1r2kr2/8/8/8/8/8/8/1R3KR1 w GBfb - 0 1"
+        );
+
+        // Standard positions keep writing "KQkq" either way.
+        assert_eq!(
+            fen_parser
+                .parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1")
+                .unwrap(),
+            Position::default()
+        );
+        // Repeated rights, even in different notations, and unknown letters are rejected.
+        for (fen, c) in [
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KHk - 0 1", 'H'),
+            ("4k3/8/8/8/8/8/8/4K2R w Ki - 0 1", 'i'),
+        ] {
+            let error = fen_parser.parse_fen(fen).unwrap_err();
+            assert_eq!(error.kind, FenErrorKind::UnexpectedCharacter(c));
+        }
+    }
+
+    #[test]
+    fn test_fen_error_display() {
+        let error = FenParser::new()
+            .parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 extra")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid FEN FullmoveNumber at character 57: unexpected text after the last field"
+        );
+    }
+
+    #[test]
+    fn test_validating_parser() {
+        let fen = "4k3/8/8/8/8/8/8/4K2p w K - 0 1";
+        assert!(FenParser::new().parse_fen(fen).is_ok());
+
+        let error = FenParser::validating().parse_fen(fen).unwrap_err();
+        assert_eq!(error.field, FenField::Position);
+        assert_eq!(
+            error.kind,
+            FenErrorKind::IllegalPosition(vec![
+                Violation::PawnOnBackRank(Square::H1),
+                Violation::CastlingWithoutRook(Castling::WHITE_OO),
+            ])
+        );
+        assert_eq!(
+            error.to_string(),
+            "invalid FEN: illegal position: pawn on h1, \
+             castling right K without its rook"
+        );
+
+        let position = FenParser::validating()
+            .parse_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")
+            .unwrap();
+        assert!(position.validate().is_empty());
+    }
 }
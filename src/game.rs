@@ -0,0 +1,301 @@
+use std::fmt;
+
+use crate::board::{Position, Undo};
+use crate::game_status::GameStatus;
+use crate::move_generator::{Move, MoveGenerator};
+use crate::pgn::{PgnGame, PgnMove, PgnResult, PgnWriter};
+use crate::san::San;
+use crate::uci::Uci;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameError {
+    // The move isn't legal in the current position.
+    IllegalMove(Move),
+    // The game has fewer plies than asked for.
+    PlyOutOfRange(usize),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::IllegalMove(mv) => {
+                write!(f, "{} is not a legal move here", Uci::format(*mv))
+            }
+            GameError::PlyOutOfRange(ply) => write!(f, "the game has no ply {}", ply),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+// A game from its start position: the moves played, the Zobrist key after every ply and the
+// current position, which can be any ply of the game. Moves after the current ply are kept
+// until a different move is pushed, so a game can be stepped through back and forth.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    start_position: Position,
+    // The position at the current ply. Its history holds the undo records of the moves
+    // played up to there.
+    position: Position,
+    moves: Vec<Move>,
+    // keys[ply] is the Zobrist key of the position after 'ply' moves, so keys[0] is the key
+    // of the start position. There is one more key than there are moves.
+    keys: Vec<u64>,
+    ply: usize,
+}
+
+impl Game {
+    // A game starting from 'start_position'. Moves made on it before are forgotten.
+    pub fn new(mut start_position: Position) -> Self {
+        start_position.history.clear();
+        let keys = vec![start_position.state.zobrist_key];
+        Self {
+            position: start_position.clone(),
+            start_position,
+            moves: Vec::new(),
+            keys,
+            ply: 0,
+        }
+    }
+
+    // The main line of a game read from PGN.
+    pub fn from_pgn(pgn_game: &PgnGame) -> Result<Self, GameError> {
+        let mut game = Game::new(pgn_game.start_position.clone());
+        for pgn_move in &pgn_game.moves {
+            game.push(pgn_move.mv)?;
+        }
+        Ok(game)
+    }
+
+    pub fn start_position(&self) -> &Position {
+        &self.start_position
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    // All moves of the game, including those after the current ply.
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    // The Zobrist key after every ply, starting with the start position.
+    pub fn keys(&self) -> &[u64] {
+        &self.keys
+    }
+
+    // The undo records of the moves played up to the current ply, oldest first.
+    pub fn undo_records(&self) -> &[Undo] {
+        &self.position.history
+    }
+
+    // How many moves have been played to reach the current position.
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    // Plays 'mv' at the current ply. Moves that followed the current ply are dropped.
+    pub fn push(&mut self, mv: Move) -> Result<(), GameError> {
+        if !MoveGenerator::generate_available_moves(&self.position).contains(&mv) {
+            return Err(GameError::IllegalMove(mv));
+        }
+        self.moves.truncate(self.ply);
+        self.keys.truncate(self.ply + 1);
+        self.position.make_move(mv);
+        self.moves.push(mv);
+        self.keys.push(self.position.state.zobrist_key);
+        self.ply += 1;
+        Ok(())
+    }
+
+    // Takes back the move that led to the current position and drops it from the game,
+    // along with every move after it.
+    pub fn pop(&mut self) -> Option<Move> {
+        let mv = *self.moves.get(self.ply.checked_sub(1)?)?;
+        self.position.unmake_move(mv);
+        self.ply -= 1;
+        self.moves.truncate(self.ply);
+        self.keys.truncate(self.ply + 1);
+        Some(mv)
+    }
+
+    // Moves to the position after 'ply' moves, keeping all moves of the game.
+    pub fn jump_to(&mut self, ply: usize) -> Result<(), GameError> {
+        if ply > self.moves.len() {
+            return Err(GameError::PlyOutOfRange(ply));
+        }
+        while self.ply > ply {
+            self.ply -= 1;
+            self.position.unmake_move(self.moves[self.ply]);
+        }
+        while self.ply < ply {
+            self.position.make_move(self.moves[self.ply]);
+            self.ply += 1;
+        }
+        Ok(())
+    }
+
+    // How many times the current position has occurred in the game up to the current ply,
+    // counting the current occurrence.
+    pub fn repetition_count(&self) -> usize {
+        self.position.repetition_count()
+    }
+
+    // Whether the game is over at the current ply and why.
+    pub fn status(&self) -> GameStatus {
+        self.position.status()
+    }
+
+    // The current position as FEN.
+    pub fn to_fen(&self) -> String {
+        self.position.to_fen()
+    }
+
+    // The whole game as a PgnGame without tags. The result is taken from the position after
+    // the last move. Draws a player would have to claim are left as "*".
+    pub fn to_pgn_game(&self) -> PgnGame {
+        let mut position = self.start_position.clone();
+        let moves = self
+            .moves
+            .iter()
+            .map(|&mv| {
+                let san = San::format(&position, mv);
+                position.make_move(mv);
+                PgnMove::new(mv, san)
+            })
+            .collect();
+        let result = match position.status() {
+            GameStatus::Draw(reason) if reason.is_claimable() => PgnResult::Unknown,
+            status => status.result(),
+        };
+        PgnGame {
+            tags: Vec::new(),
+            start_position: self.start_position.clone(),
+            moves,
+            result,
+        }
+    }
+
+    pub fn to_pgn(&self) -> String {
+        PgnWriter::to_pgn(&self.to_pgn_game())
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game::new(Position::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_status::DrawReason;
+    use crate::pgn::PgnReader;
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for uci in moves {
+            let mv = Uci::parse(game.position(), uci).unwrap();
+            game.push(mv).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut game = Game::default();
+        play(&mut game, &["e2e4", "e7e5", "g1f3"]);
+        assert_eq!(game.ply(), 3);
+        assert_eq!(game.moves().len(), 3);
+        assert_eq!(game.keys().len(), 4);
+        assert_eq!(game.undo_records().len(), 3);
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+        for (ply, &key) in game.keys().iter().enumerate() {
+            let mut replay = game.clone();
+            replay.jump_to(ply).unwrap();
+            assert_eq!(replay.position().compute_zobrist_key(), key);
+        }
+
+        let e2e4 = game.moves()[0];
+        assert_eq!(game.push(e2e4), Err(GameError::IllegalMove(e2e4)));
+
+        assert_eq!(Uci::format(game.pop().unwrap()), "g1f3");
+        assert_eq!(game.ply(), 2);
+        assert_eq!(game.moves().len(), 2);
+        assert_eq!(game.keys().len(), 3);
+        game.pop();
+        game.pop();
+        assert_eq!(game.pop(), None);
+        assert_eq!(game.position(), &Position::default());
+    }
+
+    #[test]
+    fn test_jump_to() {
+        let mut game = Game::default();
+        play(&mut game, &["d2d4", "d7d5", "c2c4"]);
+        let end = game.position().clone();
+
+        game.jump_to(1).unwrap();
+        assert_eq!(game.ply(), 1);
+        assert_eq!(game.moves().len(), 3);
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 1"
+        );
+        game.jump_to(3).unwrap();
+        assert_eq!(game.position(), &end);
+        assert_eq!(game.jump_to(4), Err(GameError::PlyOutOfRange(4)));
+
+        // Pushing a move in the middle of the game starts a new line from there.
+        game.jump_to(1).unwrap();
+        play(&mut game, &["g8f6"]);
+        assert_eq!(game.moves().len(), 2);
+        assert_eq!(game.keys().len(), 3);
+    }
+
+    #[test]
+    fn test_repetitions() {
+        let mut game = Game::default();
+        let shuffle = ["b1c3", "b8c6", "c3b1", "c6b8"];
+        play(&mut game, &shuffle);
+        assert_eq!(game.repetition_count(), 2);
+        play(&mut game, &shuffle);
+        assert_eq!(game.repetition_count(), 3);
+        assert_eq!(
+            game.status(),
+            GameStatus::Draw(DrawReason::ThreefoldRepetition)
+        );
+
+        // Stepping back counts only the plies up to the current one.
+        game.jump_to(4).unwrap();
+        assert_eq!(game.repetition_count(), 2);
+        game.jump_to(0).unwrap();
+        assert_eq!(game.repetition_count(), 1);
+    }
+
+    #[test]
+    fn test_pgn_export() {
+        let mut game = Game::default();
+        play(&mut game, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        assert_eq!(
+            game.to_pgn(),
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+             [White \"?\"]\n[Black \"?\"]\n[Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n"
+        );
+
+        // The export reads back as the same game.
+        let pgn = game.to_pgn();
+        let pgn_game = PgnReader::new(pgn.as_bytes()).next().unwrap().unwrap();
+        assert_eq!(Game::from_pgn(&pgn_game), Ok(game));
+
+        let start = Position::load_position_from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 30").unwrap();
+        let mut game = Game::new(start);
+        play(&mut game, &["e8d7", "e2e4"]);
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 30\"]"));
+        assert!(pgn.ends_with("\n30... Kd7 31. e4 *\n\n"));
+    }
+}
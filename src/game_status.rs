@@ -0,0 +1,274 @@
+use std::fmt;
+
+use crate::board::{BitBoard, Pieces, Position, Sides, Square};
+use crate::move_generator::MoveGenerator;
+use crate::pgn::PgnResult;
+
+// Why a game was drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DrawReason {
+    // The side to move has no legal moves and isn't in check.
+    Stalemate,
+    // Neither side has enough material left to deliver mate.
+    InsufficientMaterial,
+    // 75 moves by each side without a capture or pawn move. Ends the game automatically.
+    SeventyFiveMoveRule,
+    // The same position occurred five times. Ends the game automatically.
+    FivefoldRepetition,
+    // 50 moves by each side without a capture or pawn move. A player may claim the draw.
+    FiftyMoveRule,
+    // The same position occurred three times. A player may claim the draw.
+    ThreefoldRepetition,
+}
+
+impl DrawReason {
+    // Whether the draw has to be claimed by a player rather than ending the game by itself.
+    pub fn is_claimable(self) -> bool {
+        matches!(
+            self,
+            DrawReason::FiftyMoveRule | DrawReason::ThreefoldRepetition
+        )
+    }
+}
+
+impl fmt::Display for DrawReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            DrawReason::Stalemate => "stalemate",
+            DrawReason::InsufficientMaterial => "insufficient material",
+            DrawReason::SeventyFiveMoveRule => "seventy-five-move rule",
+            DrawReason::FivefoldRepetition => "fivefold repetition",
+            DrawReason::FiftyMoveRule => "fifty-move rule",
+            DrawReason::ThreefoldRepetition => "threefold repetition",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameStatus {
+    Ongoing,
+    // The side to move is checkmated. Holds the winning side.
+    Checkmate(usize),
+    Draw(DrawReason),
+}
+
+impl GameStatus {
+    pub fn is_over(self) -> bool {
+        self != GameStatus::Ongoing
+    }
+
+    // The result to record in PGN. Ongoing games are recorded as "*".
+    pub fn result(self) -> PgnResult {
+        match self {
+            GameStatus::Ongoing => PgnResult::Unknown,
+            GameStatus::Checkmate(Sides::WHITE) => PgnResult::WhiteWins,
+            GameStatus::Checkmate(_) => PgnResult::BlackWins,
+            GameStatus::Draw(_) => PgnResult::Draw,
+        }
+    }
+}
+
+impl fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameStatus::Ongoing => write!(f, "ongoing"),
+            GameStatus::Checkmate(Sides::WHITE) => write!(f, "white wins by checkmate"),
+            GameStatus::Checkmate(_) => write!(f, "black wins by checkmate"),
+            GameStatus::Draw(reason) => write!(f, "draw by {}", reason),
+        }
+    }
+}
+
+impl Position {
+    // Whether the game is over and why. Mate and stalemate take precedence over the move
+    // counting rules, and the rules that end the game automatically are reported before
+    // the ones a player has to claim.
+    pub fn status(&self) -> GameStatus {
+        if MoveGenerator::generate_available_moves(self).is_empty() {
+            return if self.in_check() {
+                GameStatus::Checkmate(self.state.side_to_move ^ 1)
+            } else {
+                GameStatus::Draw(DrawReason::Stalemate)
+            };
+        }
+
+        let repetitions = self.repetition_count();
+        let reason = if self.is_insufficient_material() {
+            DrawReason::InsufficientMaterial
+        } else if self.state.half_move_counter >= 150 {
+            DrawReason::SeventyFiveMoveRule
+        } else if repetitions >= 5 {
+            DrawReason::FivefoldRepetition
+        } else if self.state.half_move_counter >= 100 {
+            DrawReason::FiftyMoveRule
+        } else if repetitions >= 3 {
+            DrawReason::ThreefoldRepetition
+        } else {
+            return GameStatus::Ongoing;
+        };
+        GameStatus::Draw(reason)
+    }
+
+    // How many times the current position has occurred in the moves made on this
+    // position, counting the current occurrence. Positions from before the last capture
+    // or pawn move can't repeat, so only the moves since then are looked at.
+    pub fn repetition_count(&self) -> usize {
+        let key = self.state.zobrist_key;
+        let reversible = (self.state.half_move_counter as usize).min(self.history.len());
+        1 + self
+            .history
+            .iter()
+            .rev()
+            .take(reversible)
+            .skip(1)
+            .step_by(2)
+            .filter(|undo| undo.state.zobrist_key == key)
+            .count()
+    }
+
+    // Whether no sequence of legal moves can lead to mate: bare kings, a single minor
+    // piece, or any number of bishops that all stand on squares of the same colour.
+    pub fn is_insufficient_material(&self) -> bool {
+        let heavy_or_pawns = [Pieces::PAWN, Pieces::ROOK, Pieces::QUEEN]
+            .iter()
+            .any(|&piece| {
+                !(self.bb_pieces[Sides::WHITE][piece] | self.bb_pieces[Sides::BLACK][piece])
+                    .is_empty()
+            });
+        if heavy_or_pawns {
+            return false;
+        }
+
+        let knights = self.bb_pieces[Sides::WHITE][Pieces::KNIGHT]
+            | self.bb_pieces[Sides::BLACK][Pieces::KNIGHT];
+        let bishops = self.bb_pieces[Sides::WHITE][Pieces::BISHOP]
+            | self.bb_pieces[Sides::BLACK][Pieces::BISHOP];
+        if knights.count() + bishops.count() <= 1 {
+            return true;
+        }
+        knights.is_empty()
+            && ((bishops & light_squares()).is_empty() || (bishops & !light_squares()).is_empty())
+    }
+}
+
+fn light_squares() -> BitBoard {
+    let mut light = BitBoard::empty();
+    for square in Square::iter() {
+        let index = square.to_usize();
+        if (index / 8 + index % 8) % 2 == 0 {
+            light.set_bit(square);
+        }
+    }
+    light
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uci::Uci;
+
+    fn from_fen(fen: &str) -> Position {
+        Position::load_position_from_fen(fen).unwrap()
+    }
+
+    fn play(position: &mut Position, moves: &[&str]) {
+        for uci in moves {
+            let mv = Uci::parse(position, uci).unwrap();
+            position.make_move(mv);
+        }
+    }
+
+    #[test]
+    fn test_mate_and_stalemate() {
+        assert_eq!(Position::default().status(), GameStatus::Ongoing);
+
+        let mut position = Position::default();
+        play(&mut position, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        assert_eq!(position.status(), GameStatus::Checkmate(Sides::BLACK));
+        assert_eq!(position.status().result(), PgnResult::BlackWins);
+        assert_eq!(position.status().to_string(), "black wins by checkmate");
+
+        let position = from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(position.status(), GameStatus::Draw(DrawReason::Stalemate));
+        assert_eq!(position.status().result(), PgnResult::Draw);
+
+        // Mate on the move that reaches the seventy-five-move limit still counts.
+        let position = from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 150 120");
+        assert_eq!(position.status(), GameStatus::Checkmate(Sides::WHITE));
+    }
+
+    #[test]
+    fn test_move_counting_rules() {
+        let fen = |half_moves: u64| format!("4k3/8/8/8/8/8/8/R3K3 w - - {} 80", half_moves);
+        assert_eq!(from_fen(&fen(99)).status(), GameStatus::Ongoing);
+
+        let status = from_fen(&fen(100)).status();
+        assert_eq!(status, GameStatus::Draw(DrawReason::FiftyMoveRule));
+        assert!(DrawReason::FiftyMoveRule.is_claimable());
+        assert_eq!(status.to_string(), "draw by fifty-move rule");
+
+        let status = from_fen(&fen(150)).status();
+        assert_eq!(status, GameStatus::Draw(DrawReason::SeventyFiveMoveRule));
+        assert!(!DrawReason::SeventyFiveMoveRule.is_claimable());
+    }
+
+    #[test]
+    fn test_repetitions() {
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut position = Position::default();
+        assert_eq!(position.repetition_count(), 1);
+
+        play(&mut position, &shuffle);
+        assert_eq!(position.repetition_count(), 2);
+        assert_eq!(position.status(), GameStatus::Ongoing);
+
+        play(&mut position, &shuffle);
+        assert_eq!(position.repetition_count(), 3);
+        let status = position.status();
+        assert_eq!(status, GameStatus::Draw(DrawReason::ThreefoldRepetition));
+        assert!(status.is_over());
+
+        play(&mut position, &shuffle);
+        play(&mut position, &shuffle);
+        assert_eq!(position.repetition_count(), 5);
+        assert_eq!(
+            position.status(),
+            GameStatus::Draw(DrawReason::FivefoldRepetition)
+        );
+
+        // A pawn move makes every earlier position unreachable.
+        play(&mut position, &["e2e4"]);
+        play(&mut position, &["g8f6", "g1f3", "f6g8", "f3g1"]);
+        assert_eq!(position.repetition_count(), 2);
+        assert_eq!(position.status(), GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        for fen in [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KB2 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "2b1k3/8/8/8/8/8/8/3BKB2 b - - 0 1",
+        ] {
+            assert_eq!(
+                from_fen(fen).status(),
+                GameStatus::Draw(DrawReason::InsufficientMaterial),
+                "{}",
+                fen
+            );
+        }
+
+        for fen in [
+            "4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KNN1 w - - 0 1",
+            "4kn2/8/8/8/8/8/8/4KB2 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K2R w - - 0 1",
+            "4k3/8/8/8/8/8/7P/4K3 w - - 0 1",
+        ] {
+            assert!(!from_fen(fen).is_insufficient_material(), "{}", fen);
+        }
+    }
+}
@@ -1,3 +1,15 @@
+pub mod attacks;
 pub mod board;
 pub mod move_generator;
 pub mod fen_parser;
+pub mod epd;
+pub mod perft;
+pub mod pgn;
+pub mod polyglot;
+pub mod san;
+pub mod uci;
+pub mod zobrist;
+pub mod game_status;
+pub mod validation;
+pub mod chess960;
+pub mod game;
@@ -1,17 +1,21 @@
-use core::panic;
-
+use crate::attacks::Attacks;
 use crate::board::BitBoard;
+use crate::board::Castling;
+use crate::board::File;
 use crate::board::Pieces;
 use crate::board::Position;
 use crate::board::Sides;
 use crate::board::Square;
 
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
 pub struct Move {
     pub piece: usize,
     pub from: Square,
     pub to: Square,
-    pub promotion: Option<Pieces>,
-    pub capture: Option<Pieces>,
+    pub promotion: Option<usize>,
+    pub capture: Option<usize>,
+    pub en_passant: bool,
+    pub castling: bool,
 }
 
 impl Move {
@@ -22,39 +26,512 @@ impl Move {
             to,
             promotion: None,
             capture: None,
+            en_passant: false,
+            castling: false,
+        }
+    }
+}
+
+// Compact move encoding for move lists and transposition tables. Bits 0-5 hold the from
+// square, bits 6-11 the to square and bits 12-15 one of the MoveFlags below.
+// The moving piece and the captured piece are not stored and are read back from the board.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, Default)]
+pub struct PackedMove(pub u16);
+
+pub struct MoveFlags;
+impl MoveFlags {
+    pub const QUIET: u16 = 0;
+    pub const DOUBLE_PAWN_PUSH: u16 = 1;
+    pub const KING_CASTLE: u16 = 2;
+    pub const QUEEN_CASTLE: u16 = 3;
+    pub const CAPTURE: u16 = 4;
+    pub const EN_PASSANT: u16 = 5;
+    // Promotions set this bit, and the low two bits select the piece (knight, bishop, rook, queen).
+    // Promotions that capture also set CAPTURE.
+    pub const PROMOTION: u16 = 8;
+    pub const KNIGHT_PROMOTION: u16 = 8;
+    pub const BISHOP_PROMOTION: u16 = 9;
+    pub const ROOK_PROMOTION: u16 = 10;
+    pub const QUEEN_PROMOTION: u16 = 11;
+}
+
+impl PackedMove {
+    pub fn new(from: Square, to: Square, flags: u16) -> PackedMove {
+        PackedMove(from.to_usize() as u16 | (to.to_usize() as u16) << 6 | flags << 12)
+    }
+
+    pub fn from_square(self) -> Square {
+        Square::from_usize((self.0 & 0x3f) as usize).unwrap()
+    }
+
+    pub fn to_square(self) -> Square {
+        Square::from_usize((self.0 >> 6 & 0x3f) as usize).unwrap()
+    }
+
+    pub fn flags(self) -> u16 {
+        self.0 >> 12
+    }
+
+    pub fn is_capture(self) -> bool {
+        self.flags() & MoveFlags::CAPTURE != 0
+    }
+
+    pub fn is_en_passant(self) -> bool {
+        self.flags() == MoveFlags::EN_PASSANT
+    }
+
+    pub fn is_castling(self) -> bool {
+        self.flags() == MoveFlags::KING_CASTLE || self.flags() == MoveFlags::QUEEN_CASTLE
+    }
+
+    pub fn promotion(self) -> Option<usize> {
+        if self.flags() & MoveFlags::PROMOTION == 0 {
+            return None;
+        }
+        Some(Pieces::KNIGHT + (self.flags() & 0b11) as usize)
+    }
+
+    // Expands the packed move back into a Move, reading the moving and captured pieces from
+    // 'position', which must be the position the move is played in.
+    pub fn unpack(self, position: &Position) -> Move {
+        let side = position.state.side_to_move;
+        let (from, to) = (self.from_square(), self.to_square());
+        let piece = position
+            .find_piece_on(side, from)
+            .expect("packed move doesn't start on a piece of the side to move");
+        let capture = if self.is_en_passant() {
+            Some(Pieces::PAWN)
+        } else if self.is_capture() {
+            position.find_piece_on(side ^ 1, to)
+        } else {
+            None
+        };
+        Move {
+            promotion: self.promotion(),
+            capture,
+            en_passant: self.is_en_passant(),
+            castling: self.is_castling(),
+            ..Move::new(piece, from, to)
         }
     }
 }
 
+impl From<Move> for PackedMove {
+    fn from(mv: Move) -> Self {
+        let mut flags = MoveFlags::QUIET;
+        if mv.castling {
+            flags = if mv.to.file() == File::G {
+                MoveFlags::KING_CASTLE
+            } else {
+                MoveFlags::QUEEN_CASTLE
+            };
+        } else if mv.en_passant {
+            flags = MoveFlags::EN_PASSANT;
+        } else if mv.piece == Pieces::PAWN && mv.from.to_usize().abs_diff(mv.to.to_usize()) == 16 {
+            flags = MoveFlags::DOUBLE_PAWN_PUSH;
+        } else {
+            if mv.capture.is_some() {
+                flags |= MoveFlags::CAPTURE;
+            }
+            if let Some(promotion) = mv.promotion {
+                flags |= MoveFlags::PROMOTION | (promotion - Pieces::KNIGHT) as u16;
+            }
+        }
+        PackedMove::new(mv.from, mv.to, flags)
+    }
+}
+
+const PROMOTION_PIECES: [usize; 4] = [Pieces::QUEEN, Pieces::ROOK, Pieces::BISHOP, Pieces::KNIGHT];
+
 pub struct MoveGenerator {}
 impl MoveGenerator {
+    // Generates all legal moves for the side to move.
+    pub fn generate_available_moves(position: &Position) -> Vec<Move> {
+        MoveGenerator::generate_available_moves_for_side(position, position.state.side_to_move)
+    }
+
+    fn generate_available_moves_for_side(position: &Position, side: usize) -> Vec<Move> {
+        let mut available_moves = Vec::with_capacity(64);
+        let them = side ^ 1;
+        let ours = position.find_occupied_by(side);
+        let occupied = position.find_occupied();
+
+        let king = match position.king_square(side) {
+            Some(king) => king,
+            None => return available_moves,
+        };
+        let checkers = position.checkers();
 
-    pub fn generate_available_moves(position: &Position) -> BitBoard {
-        return match position.state.side_to_move {
-            Sides::WHITE => {
-                MoveGenerator::generate_available_moves_for_side(position, Sides::WHITE)
+        // The king may step anywhere that is not attacked once it has left its square,
+        // so sliders keep attacking through the square it vacates.
+        let occupied_without_king = occupied ^ BitBoard::from_square(king);
+        for to in Attacks::king(king) & !ours {
+            if position
+                .attackers_to(to, them, occupied_without_king)
+                .is_empty()
+            {
+                available_moves.push(Move {
+                    capture: position.find_piece_on(them, to),
+                    ..Move::new(Pieces::KING, king, to)
+                });
             }
-            Sides::BLACK => {
-                MoveGenerator::generate_available_moves_for_side(position, Sides::BLACK)
+        }
+
+        if checkers.count() > 1 {
+            return available_moves;
+        }
+
+        // A pinned piece may only move along the line through it and its king. A single
+        // check is answered by capturing the checker or by stepping in between.
+        let mut pin_masks = [BitBoard::full(); 64];
+        for pinned in position.pinned() {
+            pin_masks[pinned.to_usize()] = Attacks::line(king, pinned);
+        }
+        let check_mask = match checkers.lsb() {
+            Some(checker) => checkers | Attacks::between(king, checker),
+            None => BitBoard::full(),
+        };
+
+        MoveGenerator::generate_pawn_moves(
+            position,
+            side,
+            king,
+            check_mask,
+            &pin_masks,
+            &mut available_moves,
+        );
+
+        for piece in [Pieces::KNIGHT, Pieces::BISHOP, Pieces::ROOK, Pieces::QUEEN] {
+            for from in position.bb_pieces[side][piece] {
+                let targets = MoveGenerator::piece_attacks(piece, from, occupied)
+                    & !ours
+                    & check_mask
+                    & pin_masks[from.to_usize()];
+                for to in targets {
+                    available_moves.push(Move {
+                        capture: position.find_piece_on(them, to),
+                        ..Move::new(piece, from, to)
+                    });
+                }
             }
-            _ => panic!("Invalid side to move"),
         }
+
+        if checkers.is_empty() {
+            MoveGenerator::generate_castling_moves(
+                position,
+                side,
+                king,
+                occupied,
+                &mut available_moves,
+            );
+        }
+
+        available_moves
     }
 
-    pub fn capture() {
-        panic!("Not implemented yet");
+    fn generate_pawn_moves(
+        position: &Position,
+        side: usize,
+        king: Square,
+        check_mask: BitBoard,
+        pin_masks: &[BitBoard; 64],
+        available_moves: &mut Vec<Move>,
+    ) {
+        let them = side ^ 1;
+        let theirs = position.find_occupied_by(them);
+        let occupied = position.find_occupied();
+        let (forward, start_row) = match side {
+            Sides::WHITE => (1, 6),
+            _ => (-1, 1),
+        };
+
+        for from in position.bb_pieces[side][Pieces::PAWN] {
+            let allowed = check_mask & pin_masks[from.to_usize()];
+
+            if let Some(one) = from.offset(0, forward) {
+                if !occupied.is_bit_set(one) {
+                    if allowed.is_bit_set(one) {
+                        MoveGenerator::push_pawn_move(available_moves, from, one, None);
+                    }
+                    if from.to_usize() / 8 == start_row {
+                        if let Some(two) = one.offset(0, forward) {
+                            if !occupied.is_bit_set(two) && allowed.is_bit_set(two) {
+                                available_moves.push(Move::new(Pieces::PAWN, from, two));
+                            }
+                        }
+                    }
+                }
+            }
+
+            let attacks = Attacks::pawn(from, side);
+            for to in attacks & theirs & allowed {
+                let capture = position.find_piece_on(them, to);
+                MoveGenerator::push_pawn_move(available_moves, from, to, capture);
+            }
+
+            // En passant removes two pawns from the same rank, which can uncover a check
+            // that the pin masks don't see, so the resulting occupancy is checked directly.
+            if let Some(ep_square) = position.state.en_passant_square {
+                if attacks.is_bit_set(ep_square) {
+                    let captured = ep_square.offset(0, -forward).unwrap();
+                    let occupied_after = occupied
+                        ^ BitBoard::from_square(from)
+                        ^ BitBoard::from_square(captured)
+                        ^ BitBoard::from_square(ep_square);
+                    let attackers = position.attackers_to(king, them, occupied_after);
+                    if (attackers & occupied_after).is_empty() {
+                        available_moves.push(Move {
+                            capture: Some(Pieces::PAWN),
+                            en_passant: true,
+                            ..Move::new(Pieces::PAWN, from, ep_square)
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn push_pawn_move(
+        available_moves: &mut Vec<Move>,
+        from: Square,
+        to: Square,
+        capture: Option<usize>,
+    ) {
+        let to_row = to.to_usize() / 8;
+        if to_row == 0 || to_row == 7 {
+            for promotion in PROMOTION_PIECES {
+                available_moves.push(Move {
+                    promotion: Some(promotion),
+                    capture,
+                    ..Move::new(Pieces::PAWN, from, to)
+                });
+            }
+        } else {
+            available_moves.push(Move {
+                capture,
+                ..Move::new(Pieces::PAWN, from, to)
+            });
+        }
+    }
+
+    // Castling follows the Chess960 rules, which standard chess is a special case of: the
+    // king and the rook may only pass over or land on empty squares, apart from each other,
+    // and the king may not pass over or land on an attacked square.
+    fn generate_castling_moves(
+        position: &Position,
+        side: usize,
+        king: Square,
+        occupied: BitBoard,
+        available_moves: &mut Vec<Move>,
+    ) {
+        let them = side ^ 1;
+        let rights = &position.state.castling_rights;
+        for right in [Castling::right(side, true), Castling::right(side, false)] {
+            if !rights.has(right) {
+                continue;
+            }
+            let rook = rights.rook_square(right);
+            if !position.bb_pieces[side][Pieces::ROOK].is_bit_set(rook)
+                || king.rank() != rook.rank()
+            {
+                continue;
+            }
+            let (king_to, rook_to) = Castling::destinations(right);
+            let others = occupied ^ BitBoard::from_square(king) ^ BitBoard::from_square(rook);
+            let king_path = MoveGenerator::rank_span(king, king_to);
+            let rook_path = MoveGenerator::rank_span(rook, rook_to);
+            if !((king_path | rook_path) & others).is_empty() {
+                continue;
+            }
+            // The rook is left out of the occupancy, so a Chess960 rook that shields the
+            // king's destination from an enemy slider doesn't hide the attack.
+            if king_path
+                .iter()
+                .any(|square| !position.attackers_to(square, them, others).is_empty())
+            {
+                continue;
+            }
+            available_moves.push(Move {
+                castling: true,
+                ..Move::new(Pieces::KING, king, king_to)
+            });
+        }
+    }
+
+    // The squares from 'a' to 'b', both included, which must be on the same rank.
+    fn rank_span(a: Square, b: Square) -> BitBoard {
+        let (a, b) = (a.to_usize(), b.to_usize());
+        (a.min(b)..=a.max(b))
+            .filter_map(Square::from_usize)
+            .fold(BitBoard::empty(), |span, square| {
+                span | BitBoard::from_square(square)
+            })
+    }
+
+    fn piece_attacks(piece: usize, square: Square, occupied: BitBoard) -> BitBoard {
+        match piece {
+            Pieces::KNIGHT => Attacks::knight(square),
+            Pieces::BISHOP => Attacks::bishop(square, occupied),
+            Pieces::ROOK => Attacks::rook(square, occupied),
+            Pieces::QUEEN => Attacks::queen(square, occupied),
+            Pieces::KING => Attacks::king(square),
+            _ => panic!("Invalid piece type"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen_parser::FenParser;
+
+    #[test]
+    fn test_packed_move_size() {
+        assert_eq!(std::mem::size_of::<PackedMove>(), 2);
+    }
+
+    #[test]
+    fn test_packed_move_fields() {
+        let packed = PackedMove::new(
+            Square::E7,
+            Square::D8,
+            MoveFlags::QUEEN_PROMOTION | MoveFlags::CAPTURE,
+        );
+        assert_eq!(packed.from_square(), Square::E7);
+        assert_eq!(packed.to_square(), Square::D8);
+        assert!(packed.is_capture());
+        assert!(!packed.is_castling());
+        assert_eq!(packed.promotion(), Some(Pieces::QUEEN));
+
+        let packed = PackedMove::new(Square::E1, Square::C1, MoveFlags::QUEEN_CASTLE);
+        assert!(packed.is_castling());
+        assert!(!packed.is_capture());
+        assert_eq!(packed.promotion(), None);
+    }
+
+    #[test]
+    fn test_packed_move_round_trip() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ];
+        for fen in fens {
+            let position = FenParser::new().parse_fen(fen).unwrap();
+            for mv in MoveGenerator::generate_available_moves(&position) {
+                let packed = PackedMove::from(mv);
+                assert_eq!(packed.unpack(&position), mv, "{}", fen);
+            }
+        }
+    }
+
+    fn moves_from_fen(fen: &str) -> Vec<Move> {
+        let position = FenParser::new().parse_fen(fen).unwrap();
+        MoveGenerator::generate_available_moves(&position)
     }
 
-    fn generate_available_moves_for_side(position: &Position, side: usize) -> BitBoard {
-        let mut available_moves = BitBoard::empty();
-        let mut pawns = position.bb_pieces[side][Pieces::PAWN];
-        // Iter over pawns
-        /* for pawn in pawns.iter() {
-            let pawn_square = pawn.square();
-            let pawn_moves = MoveGenerator::generate_pawn_moves(position, pawn_square, side);
-            available_moves = available_moves | pawn_moves;
-        } */
-        
-        return available_moves;
+    #[test]
+    fn test_generate_moves_start_position() {
+        let moves = MoveGenerator::generate_available_moves(&Position::default());
+        assert_eq!(moves.len(), 20);
+        assert_eq!(
+            moves.iter().filter(|mv| mv.piece == Pieces::PAWN).count(),
+            16
+        );
+        assert_eq!(
+            moves.iter().filter(|mv| mv.piece == Pieces::KNIGHT).count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_generate_moves_reference_positions() {
+        let positions = [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                48,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 14),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                6,
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                44,
+            ),
+            (
+                "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+                46,
+            ),
+        ];
+        for (fen, expected) in positions {
+            assert_eq!(moves_from_fen(fen).len(), expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_generate_moves_castling() {
+        let moves = moves_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let castles: Vec<&Move> = moves.iter().filter(|mv| mv.castling).collect();
+        assert_eq!(castles.len(), 2);
+        assert!(castles.iter().any(|mv| mv.to == Square::G1));
+        assert!(castles.iter().any(|mv| mv.to == Square::C1));
+
+        // The black rook on f8 covers f1, so white can't castle king-side.
+        let moves = moves_from_fen("r3kr2/8/8/8/8/8/8/R3K2R w KQq - 0 1");
+        let castles: Vec<&Move> = moves.iter().filter(|mv| mv.castling).collect();
+        assert_eq!(castles.len(), 1);
+        assert_eq!(castles[0].to, Square::C1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_generate_moves_promotions() {
+        let moves = moves_from_fen("3n4/4P3/8/8/8/8/8/K6k w - - 0 1");
+        let promotions: Vec<&Move> = moves.iter().filter(|mv| mv.promotion.is_some()).collect();
+        assert_eq!(promotions.len(), 8);
+        assert_eq!(
+            promotions
+                .iter()
+                .filter(|mv| mv.capture == Some(Pieces::KNIGHT))
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_generate_moves_en_passant() {
+        let moves = moves_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        let en_passant: Vec<&Move> = moves.iter().filter(|mv| mv.en_passant).collect();
+        assert_eq!(en_passant.len(), 1);
+        assert_eq!(en_passant[0].from, Square::E5);
+        assert_eq!(en_passant[0].to, Square::D6);
+        assert_eq!(en_passant[0].capture, Some(Pieces::PAWN));
+
+        // Capturing en passant would expose the king to the rook along the fifth rank.
+        let moves = moves_from_fen("8/8/8/K2pP2r/8/8/8/7k w - d6 0 1");
+        assert!(!moves.iter().any(|mv| mv.en_passant));
+    }
+
+    #[test]
+    fn test_generate_moves_pinned_piece() {
+        // The knight on e2 is pinned by the rook on e8 and can't move.
+        let moves = moves_from_fen("4r2k/8/8/8/8/8/4N3/4K3 w - - 0 1");
+        assert!(!moves.iter().any(|mv| mv.piece == Pieces::KNIGHT));
+    }
+
+    #[test]
+    fn test_generate_moves_double_check() {
+        // Only the king may move when it is attacked twice.
+        let moves = moves_from_fen("4r2k/8/8/8/8/5n2/3Q4/4K3 w - - 0 1");
+        assert!(!moves.is_empty());
+        assert!(moves.iter().all(|mv| mv.piece == Pieces::KING));
+    }
+
+    #[test]
+    fn test_generate_moves_checkmate() {
+        let moves = moves_from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert!(moves.is_empty());
+    }
+}
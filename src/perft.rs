@@ -0,0 +1,198 @@
+use crate::board::Position;
+use crate::move_generator::Move;
+use crate::move_generator::MoveGenerator;
+use crate::uci::Uci;
+
+pub struct Perft;
+
+impl Perft {
+    // Counts the leaf nodes of the legal move tree 'depth' plies deep.
+    pub fn perft(position: &mut Position, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = MoveGenerator::generate_available_moves(position);
+        // Every legal move at the last ply is a leaf, so there is no need to make them.
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut nodes = 0;
+        for mv in moves {
+            position.make_move(mv);
+            nodes += Perft::perft(position, depth - 1);
+            position.unmake_move(mv);
+        }
+        nodes
+    }
+
+    // Runs perft below every root move and prints the count for each one, which makes it
+    // easy to find the move where two move generators disagree. Returns the total.
+    pub fn divide(position: &mut Position, depth: u32) -> u64 {
+        let counts = Perft::divide_counts(position, depth);
+        print!("{}", Perft::format_divide(position, &counts));
+        counts.iter().map(|&(_, nodes)| nodes).sum()
+    }
+
+    // The text divide prints for 'counts': a "move: nodes" line per root move, then the
+    // total. Moves are written as other engines write them: standard castling as the king's
+    // step, e.g. "e1g1", and Chess960 castling as the king taking its rook, since the king
+    // may already stand on its castling square.
+    pub fn format_divide(position: &Position, counts: &[(Move, u64)]) -> String {
+        let chess960 = !position.state.castling_rights.is_standard();
+        let mut output = String::new();
+        for &(mv, nodes) in counts {
+            let uci = if chess960 {
+                Uci::format_chess960(position, mv)
+            } else {
+                Uci::format(mv)
+            };
+            output += &format!("{}: {}\n", uci, nodes);
+        }
+        let total: u64 = counts.iter().map(|&(_, nodes)| nodes).sum();
+        output += &format!("\nNodes searched: {}\n", total);
+        output
+    }
+
+    // Perft counts below every root move, in move generation order.
+    pub fn divide_counts(position: &mut Position, depth: u32) -> Vec<(Move, u64)> {
+        if depth == 0 {
+            return Vec::new();
+        }
+        MoveGenerator::generate_available_moves(position)
+            .into_iter()
+            .map(|mv| {
+                position.make_move(mv);
+                let nodes = Perft::perft(position, depth - 1);
+                position.unmake_move(mv);
+                (mv, nodes)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn perft_from_fen(fen: &str, depth: u32) -> u64 {
+        let mut position = Position::load_position_from_fen(fen).unwrap();
+        Perft::perft(&mut position, depth)
+    }
+
+    #[test]
+    fn test_perft_start_position() {
+        let mut position = Position::default();
+        assert_eq!(Perft::perft(&mut position, 0), 1);
+        assert_eq!(Perft::perft(&mut position, 1), 20);
+        assert_eq!(Perft::perft(&mut position, 2), 400);
+        assert_eq!(Perft::perft(&mut position, 3), 8_902);
+        assert_eq!(Perft::perft(&mut position, 4), 197_281);
+        assert_eq!(position, Position::default());
+    }
+
+    #[test]
+    fn test_perft_kiwipete() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(perft_from_fen(fen, 1), 48);
+        assert_eq!(perft_from_fen(fen, 2), 2_039);
+        assert_eq!(perft_from_fen(fen, 3), 97_862);
+    }
+
+    #[test]
+    fn test_perft_position_3() {
+        let fen = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+        assert_eq!(perft_from_fen(fen, 1), 14);
+        assert_eq!(perft_from_fen(fen, 2), 191);
+        assert_eq!(perft_from_fen(fen, 3), 2_812);
+        assert_eq!(perft_from_fen(fen, 4), 43_238);
+        assert_eq!(perft_from_fen(fen, 5), 674_624);
+    }
+
+    #[test]
+    fn test_perft_position_4() {
+        let fen = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        assert_eq!(perft_from_fen(fen, 1), 6);
+        assert_eq!(perft_from_fen(fen, 2), 264);
+        assert_eq!(perft_from_fen(fen, 3), 9_467);
+        assert_eq!(perft_from_fen(fen, 4), 422_333);
+
+        // The same position with colors reversed.
+        let fen = "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1";
+        assert_eq!(perft_from_fen(fen, 3), 9_467);
+    }
+
+    #[test]
+    fn test_perft_position_5() {
+        let fen = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+        assert_eq!(perft_from_fen(fen, 1), 44);
+        assert_eq!(perft_from_fen(fen, 2), 1_486);
+        assert_eq!(perft_from_fen(fen, 3), 62_379);
+    }
+
+    #[test]
+    fn test_perft_position_6() {
+        let fen = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+        assert_eq!(perft_from_fen(fen, 1), 46);
+        assert_eq!(perft_from_fen(fen, 2), 2_079);
+        assert_eq!(perft_from_fen(fen, 3), 89_890);
+    }
+
+    // Positions from the Chess960 perft suite, with Shredder-FEN castling rights.
+    #[test]
+    fn test_perft_chess960() {
+        let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        assert_eq!(perft_from_fen(fen, 1), 21);
+        assert_eq!(perft_from_fen(fen, 2), 528);
+        assert_eq!(perft_from_fen(fen, 3), 12_189);
+        assert_eq!(perft_from_fen(fen, 4), 326_672);
+
+        let fen = "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9";
+        assert_eq!(perft_from_fen(fen, 1), 21);
+        assert_eq!(perft_from_fen(fen, 2), 807);
+        assert_eq!(perft_from_fen(fen, 3), 18_002);
+        assert_eq!(perft_from_fen(fen, 4), 667_366);
+
+        let fen = "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9";
+        assert_eq!(perft_from_fen(fen, 1), 20);
+        assert_eq!(perft_from_fen(fen, 2), 479);
+        assert_eq!(perft_from_fen(fen, 3), 10_471);
+        assert_eq!(perft_from_fen(fen, 4), 273_318);
+    }
+
+    #[test]
+    fn test_divide_start_position() {
+        let mut position = Position::default();
+        let counts = Perft::divide_counts(&mut position, 3);
+        assert_eq!(counts.len(), 20);
+        let e2e4 = counts
+            .iter()
+            .find(|(mv, _)| Uci::format(*mv) == "e2e4")
+            .unwrap();
+        assert_eq!(e2e4.1, 600);
+        assert_eq!(Perft::divide(&mut position, 3), 8_902);
+    }
+
+    #[test]
+    fn test_divide_kiwipete() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut position = Position::load_position_from_fen(fen).unwrap();
+        let counts = Perft::divide_counts(&mut position, 2);
+        let output = Perft::format_divide(&position, &counts);
+        assert!(output.lines().any(|line| line.starts_with("e1g1: ")));
+        assert!(output.lines().any(|line| line.starts_with("e1c1: ")));
+        assert!(output.ends_with("\nNodes searched: 2039\n"));
+    }
+
+    #[test]
+    fn test_divide_chess960() {
+        // The king already stands on g1, so castling king side only moves the rook.
+        let fen = "1r4kr/8/8/8/8/8/8/1R4KR w HBhb - 0 1";
+        let mut position = Position::load_position_from_fen(fen).unwrap();
+        let counts = Perft::divide_counts(&mut position, 2);
+        let output = Perft::format_divide(&position, &counts);
+        assert!(output.lines().any(|line| line.starts_with("g1h1: ")));
+        assert!(output.lines().any(|line| line.starts_with("g1b1: ")));
+        assert!(!output.contains("g1g1"));
+        assert_eq!(Perft::divide(&mut position, 2), perft_from_fen(fen, 2));
+    }
+}
@@ -0,0 +1,945 @@
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use crate::board::Position;
+use crate::fen_parser::FenError;
+use crate::move_generator::Move;
+use crate::san::San;
+use crate::san::SanError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgnResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    // The game is still in progress, was abandoned or the result is unknown.
+    Unknown,
+}
+
+impl PgnResult {
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "1-0" => Some(PgnResult::WhiteWins),
+            "0-1" => Some(PgnResult::BlackWins),
+            "1/2-1/2" => Some(PgnResult::Draw),
+            "*" => Some(PgnResult::Unknown),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PgnResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let token = match self {
+            PgnResult::WhiteWins => "1-0",
+            PgnResult::BlackWins => "0-1",
+            PgnResult::Draw => "1/2-1/2",
+            PgnResult::Unknown => "*",
+        };
+        write!(f, "{}", token)
+    }
+}
+
+// A move of a game together with the annotations attached to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnMove {
+    pub mv: Move,
+    pub san: String,
+    // Numeric annotation glyphs. Suffixes such as '!' or '?!' are stored as their NAG.
+    pub nags: Vec<u8>,
+    // A comment in front of the move, only found at the start of a game or variation.
+    pub comment_before: Option<String>,
+    pub comment_after: Option<String>,
+    // Alternatives to this move, each one played from the position before it.
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(mv: Move, san: String) -> Self {
+        Self {
+            mv,
+            san,
+            nags: Vec::new(),
+            comment_before: None,
+            comment_after: None,
+            variations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnGame {
+    // Tag pairs in the order they appear in the file.
+    pub tags: Vec<(String, String)>,
+    // The standard start position, or the position given by the FEN tag.
+    pub start_position: Position,
+    // The main line.
+    pub moves: Vec<PgnMove>,
+    pub result: PgnResult,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The position at the end of the main line.
+    pub fn final_position(&self) -> Position {
+        let mut position = self.start_position.clone();
+        for pgn_move in &self.moves {
+            position.make_move(pgn_move.mv);
+        }
+        position
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnErrorKind {
+    // The underlying reader failed. Reading stops after this error.
+    Io(String),
+    // A tag pair that isn't shaped like [Name "value"].
+    InvalidTag,
+    // The FEN tag doesn't hold a valid position.
+    InvalidFen(FenError),
+    // A move that can't be played in the position it appears in.
+    InvalidMove(SanError),
+    // A token that isn't allowed where it appears.
+    UnexpectedToken(String),
+    UnterminatedComment,
+    UnterminatedString,
+    // A variation that isn't closed before the end of the game.
+    UnterminatedVariation,
+}
+
+// Describes why a game could not be read and the line of the file where the problem was
+// found. Only the game containing the problem is lost, the reader continues with the next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnError {
+    pub line: usize,
+    pub kind: PgnErrorKind,
+}
+
+impl PgnError {
+    fn new(line: usize, kind: PgnErrorKind) -> Self {
+        Self { line, kind }
+    }
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match &self.kind {
+            PgnErrorKind::Io(error) => format!("read failed: {}", error),
+            PgnErrorKind::InvalidTag => "invalid tag pair".to_string(),
+            PgnErrorKind::InvalidFen(error) => error.to_string(),
+            PgnErrorKind::InvalidMove(error) => error.to_string(),
+            PgnErrorKind::UnexpectedToken(token) => format!("unexpected '{}'", token),
+            PgnErrorKind::UnterminatedComment => "comment is never closed".to_string(),
+            PgnErrorKind::UnterminatedString => "string is never closed".to_string(),
+            PgnErrorKind::UnterminatedVariation => "variation is never closed".to_string(),
+        };
+        write!(f, "invalid PGN on line {}: {}", self.line, reason)
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+// Reads games one at a time from a PGN file, e.g.
+// `for game in PgnReader::new(BufReader::new(file)) { ... }`.
+pub struct PgnReader<R> {
+    reader: R,
+    line_number: usize,
+    // The first tag line of the next game, read while looking for the end of the previous one.
+    pending: Option<(usize, String)>,
+    finished: bool,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line_number: 0,
+            pending: None,
+            finished: false,
+        }
+    }
+
+    fn read_line(&mut self) -> Result<Option<String>, PgnError> {
+        let mut bytes = Vec::new();
+        match self.reader.read_until(b'\n', &mut bytes) {
+            Ok(0) => Ok(None),
+            Ok(_) => {
+                self.line_number += 1;
+                let line = String::from_utf8_lossy(&bytes);
+                Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+            }
+            Err(error) => Err(PgnError::new(
+                self.line_number + 1,
+                PgnErrorKind::Io(error.to_string()),
+            )),
+        }
+    }
+
+    // Reads the lines of the next game: its tag pairs and the movetext up to the next
+    // tag pair that isn't inside a comment.
+    fn read_game_lines(&mut self) -> Result<Vec<(usize, String)>, PgnError> {
+        let mut lines: Vec<(usize, String)> = self.pending.take().into_iter().collect();
+        let mut in_movetext = false;
+        let mut in_comment = false;
+        while !self.finished {
+            let text = match self.read_line() {
+                Ok(Some(text)) => text,
+                Ok(None) => {
+                    self.finished = true;
+                    break;
+                }
+                Err(error) => {
+                    self.finished = true;
+                    return Err(error);
+                }
+            };
+            let trimmed = text.trim_start();
+            if !in_comment {
+                if trimmed.starts_with('[') && in_movetext {
+                    self.pending = Some((self.line_number, text));
+                    break;
+                }
+                if !trimmed.is_empty() && !trimmed.starts_with(['[', '%']) {
+                    in_movetext = true;
+                }
+            }
+            in_comment = PgnReader::<R>::ends_inside_comment(&text, in_comment);
+            lines.push((self.line_number, text));
+        }
+        Ok(lines)
+    }
+
+    // Whether a brace comment is still open at the end of 'line'.
+    fn ends_inside_comment(line: &str, mut in_comment: bool) -> bool {
+        let mut in_string = false;
+        for c in line.chars() {
+            match c {
+                '}' if in_comment => in_comment = false,
+                _ if in_comment => {}
+                '"' => in_string = !in_string,
+                '{' if !in_string => in_comment = true,
+                ';' if !in_string => break,
+                _ => {}
+            }
+        }
+        in_comment
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let lines = match self.read_game_lines() {
+                Ok(lines) => lines,
+                Err(error) => return Some(Err(error)),
+            };
+            if lines.is_empty() {
+                return None;
+            }
+            // Text with nothing but comments, e.g. a header at the top of the file, is skipped.
+            match PgnParser::parse_game(&lines) {
+                Ok(Some(game)) => return Some(Ok(game)),
+                Ok(None) => continue,
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    OpenBracket,
+    CloseBracket,
+    OpenParen,
+    CloseParen,
+    Period,
+    Str(String),
+    Symbol(String),
+    Comment(String),
+    Nag(u8),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::OpenBracket => write!(f, "["),
+            Token::CloseBracket => write!(f, "]"),
+            Token::OpenParen => write!(f, "("),
+            Token::CloseParen => write!(f, ")"),
+            Token::Period => write!(f, "."),
+            Token::Str(text) => write!(f, "\"{}\"", text),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+            Token::Comment(text) => write!(f, "{{{}}}", text),
+            Token::Nag(nag) => write!(f, "${}", nag),
+        }
+    }
+}
+
+struct PgnParser {
+    tokens: Peekable<IntoIter<(usize, Token)>>,
+    line: usize,
+}
+
+impl PgnParser {
+    // Parses the lines of one game. Returns None if they hold nothing but comments.
+    fn parse_game(lines: &[(usize, String)]) -> Result<Option<PgnGame>, PgnError> {
+        let tokens = PgnParser::tokenize(lines)?;
+        if tokens
+            .iter()
+            .all(|(_, token)| matches!(token, Token::Comment(_)))
+        {
+            return Ok(None);
+        }
+        let mut parser = PgnParser {
+            tokens: tokens.into_iter().peekable(),
+            line: lines.first().map_or(1, |(line, _)| *line),
+        };
+
+        let mut tags = Vec::new();
+        let mut fen = None;
+        while let Some((_, Token::OpenBracket)) = parser.tokens.peek() {
+            let (line, name, value) = parser.parse_tag()?;
+            if name == "FEN" {
+                fen = Some((line, value.clone()));
+            }
+            tags.push((name, value));
+        }
+
+        let start_position = match fen {
+            Some((line, fen)) => Position::load_position_from_fen(&fen)
+                .map_err(|error| PgnError::new(line, PgnErrorKind::InvalidFen(error)))?,
+            None => Position::default(),
+        };
+        let mut position = start_position.clone();
+        let (moves, result) = parser.parse_line(&mut position, false)?;
+
+        for (line, token) in parser.tokens {
+            if !matches!(token, Token::Comment(_)) {
+                return Err(PgnError::new(
+                    line,
+                    PgnErrorKind::UnexpectedToken(token.to_string()),
+                ));
+            }
+        }
+
+        Ok(Some(PgnGame {
+            tags,
+            start_position,
+            moves,
+            result: result.unwrap_or(PgnResult::Unknown),
+        }))
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let (line, token) = self.tokens.next()?;
+        self.line = line;
+        Some(token)
+    }
+
+    fn parse_tag(&mut self) -> Result<(usize, String, String), PgnError> {
+        self.next_token();
+        let line = self.line;
+        let name = match self.next_token() {
+            Some(Token::Symbol(name)) => name,
+            _ => return Err(PgnError::new(self.line, PgnErrorKind::InvalidTag)),
+        };
+        let value = match self.next_token() {
+            Some(Token::Str(value)) => value,
+            _ => return Err(PgnError::new(self.line, PgnErrorKind::InvalidTag)),
+        };
+        match self.next_token() {
+            Some(Token::CloseBracket) => Ok((line, name, value)),
+            _ => Err(PgnError::new(self.line, PgnErrorKind::InvalidTag)),
+        }
+    }
+
+    // Parses moves until the end of the game, or until the closing parenthesis if this is
+    // a variation. Returns the result token that ended the main line, if there was one.
+    fn parse_line(
+        &mut self,
+        position: &mut Position,
+        in_variation: bool,
+    ) -> Result<(Vec<PgnMove>, Option<PgnResult>), PgnError> {
+        let mut moves: Vec<PgnMove> = Vec::new();
+        let mut comment_before = None;
+        loop {
+            let token = match self.next_token() {
+                Some(token) => token,
+                None if in_variation => {
+                    return Err(PgnError::new(
+                        self.line,
+                        PgnErrorKind::UnterminatedVariation,
+                    ))
+                }
+                None => return Ok((moves, None)),
+            };
+            let unexpected = |line, token: &Token| {
+                PgnError::new(line, PgnErrorKind::UnexpectedToken(token.to_string()))
+            };
+            match token {
+                Token::Period => {}
+                Token::Comment(text) => match moves.last_mut() {
+                    Some(last) => PgnParser::append_comment(&mut last.comment_after, text),
+                    None => PgnParser::append_comment(&mut comment_before, text),
+                },
+                Token::Nag(nag) => match moves.last_mut() {
+                    Some(last) => last.nags.push(nag),
+                    None => return Err(unexpected(self.line, &token)),
+                },
+                Token::OpenParen => {
+                    let line = self.line;
+                    let Some(last) = moves.last() else {
+                        return Err(unexpected(line, &token));
+                    };
+                    let mut before = position.clone();
+                    before.unmake_move(last.mv);
+                    let (variation, _) = self.parse_line(&mut before, true)?;
+                    if let Some(last) = moves.last_mut() {
+                        last.variations.push(variation);
+                    }
+                }
+                Token::CloseParen if in_variation => return Ok((moves, None)),
+                Token::Symbol(symbol) => {
+                    if let Some(result) = PgnResult::from_token(&symbol) {
+                        if in_variation {
+                            return Err(unexpected(self.line, &Token::Symbol(symbol)));
+                        }
+                        return Ok((moves, Some(result)));
+                    }
+                    // Move numbers. The periods after them are separate tokens.
+                    if symbol.chars().all(|c| c.is_ascii_digit()) {
+                        continue;
+                    }
+                    let mv = San::parse(position, &symbol).map_err(|error| {
+                        PgnError::new(self.line, PgnErrorKind::InvalidMove(error))
+                    })?;
+                    let mut pgn_move = PgnMove::new(mv, San::format(position, mv));
+                    pgn_move.comment_before = comment_before.take();
+                    position.make_move(mv);
+                    moves.push(pgn_move);
+                }
+                _ => return Err(unexpected(self.line, &token)),
+            }
+        }
+    }
+
+    fn append_comment(comment: &mut Option<String>, text: String) {
+        match comment {
+            Some(existing) => {
+                existing.push(' ');
+                existing.push_str(&text);
+            }
+            None => *comment = Some(text),
+        }
+    }
+
+    fn tokenize(lines: &[(usize, String)]) -> Result<Vec<(usize, Token)>, PgnError> {
+        let mut tokens = Vec::new();
+        // The line a brace comment started on and its text so far.
+        let mut comment: Option<(usize, String)> = None;
+        for (line, text) in lines {
+            let line = *line;
+            // Lines starting with '%' are escaped from PGN processing.
+            if comment.is_none() && text.starts_with('%') {
+                continue;
+            }
+            let mut chars = text.chars().peekable();
+            while let Some(c) = chars.next() {
+                if let Some((start, body)) = comment.as_mut() {
+                    if c == '}' {
+                        tokens.push((*start, Token::Comment(PgnParser::collapse(body))));
+                        comment = None;
+                    } else {
+                        body.push(c);
+                    }
+                    continue;
+                }
+                let token = match c {
+                    '{' => {
+                        comment = Some((line, String::new()));
+                        continue;
+                    }
+                    ';' => Token::Comment(PgnParser::collapse(&chars.by_ref().collect::<String>())),
+                    '[' => Token::OpenBracket,
+                    ']' => Token::CloseBracket,
+                    '(' => Token::OpenParen,
+                    ')' => Token::CloseParen,
+                    '.' => Token::Period,
+                    '*' => Token::Symbol("*".to_string()),
+                    '"' => {
+                        let mut value = String::new();
+                        loop {
+                            match chars.next() {
+                                Some('"') => break,
+                                Some('\\') => match chars.next() {
+                                    Some(escaped) => value.push(escaped),
+                                    None => break,
+                                },
+                                Some(c) => value.push(c),
+                                None => {
+                                    return Err(PgnError::new(
+                                        line,
+                                        PgnErrorKind::UnterminatedString,
+                                    ))
+                                }
+                            }
+                        }
+                        Token::Str(value)
+                    }
+                    '$' => {
+                        let digits = PgnParser::take_while(&mut chars, |c| c.is_ascii_digit());
+                        match digits.parse() {
+                            Ok(nag) => Token::Nag(nag),
+                            Err(_) => {
+                                return Err(PgnError::new(
+                                    line,
+                                    PgnErrorKind::UnexpectedToken(format!("${}", digits)),
+                                ))
+                            }
+                        }
+                    }
+                    '!' | '?' => {
+                        let suffix = format!(
+                            "{}{}",
+                            c,
+                            PgnParser::take_while(&mut chars, |c| c == '!' || c == '?')
+                        );
+                        match suffix.as_str() {
+                            "!" => Token::Nag(1),
+                            "?" => Token::Nag(2),
+                            "!!" => Token::Nag(3),
+                            "??" => Token::Nag(4),
+                            "!?" => Token::Nag(5),
+                            "?!" => Token::Nag(6),
+                            _ => {
+                                return Err(PgnError::new(
+                                    line,
+                                    PgnErrorKind::UnexpectedToken(suffix),
+                                ))
+                            }
+                        }
+                    }
+                    c if c.is_whitespace() => continue,
+                    c if PgnParser::is_symbol_char(c) => Token::Symbol(format!(
+                        "{}{}",
+                        c,
+                        PgnParser::take_while(&mut chars, PgnParser::is_symbol_char)
+                    )),
+                    c => {
+                        return Err(PgnError::new(
+                            line,
+                            PgnErrorKind::UnexpectedToken(c.to_string()),
+                        ))
+                    }
+                };
+                tokens.push((line, token));
+            }
+            if let Some((_, body)) = comment.as_mut() {
+                body.push(' ');
+            }
+        }
+        match comment {
+            Some((start, _)) => Err(PgnError::new(start, PgnErrorKind::UnterminatedComment)),
+            None => Ok(tokens),
+        }
+    }
+
+    fn take_while(chars: &mut Peekable<std::str::Chars>, accept: fn(char) -> bool) -> String {
+        let mut taken = String::new();
+        while let Some(&c) = chars.peek() {
+            if !accept(c) {
+                break;
+            }
+            taken.push(c);
+            chars.next();
+        }
+        taken
+    }
+
+    fn is_symbol_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || "_+#=:-/".contains(c)
+    }
+
+    // Comments may span lines. Their whitespace is collapsed to single spaces.
+    fn collapse(text: &str) -> String {
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+// The tags every PGN game must have, in the order they must appear, with the values used
+// when a game doesn't have them.
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+// Movetext lines are wrapped to stay within this many characters.
+const MAX_LINE_LENGTH: usize = 80;
+
+// Writes games in export format: the seven tag roster first, SetUp and FEN tags when the
+// game doesn't start from the standard position, then the movetext wrapped to 80 columns.
+pub struct PgnWriter;
+
+impl PgnWriter {
+    pub fn write<W: Write>(writer: &mut W, game: &PgnGame) -> io::Result<()> {
+        writer.write_all(PgnWriter::to_pgn(game).as_bytes())
+    }
+
+    // The game as PGN text, followed by the blank line that separates it from the next game.
+    pub fn to_pgn(game: &PgnGame) -> String {
+        let mut pgn = String::new();
+        for (name, value) in PgnWriter::export_tags(game) {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, PgnWriter::escape(&value)));
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        let first_ply = game.start_position.state.ply as usize;
+        PgnWriter::line_tokens(&mut tokens, &game.moves, first_ply);
+        tokens.push(game.result.to_string());
+        for line in PgnWriter::wrap(&tokens) {
+            pgn.push_str(&line);
+            pgn.push('\n');
+        }
+        pgn.push('\n');
+        pgn
+    }
+
+    fn export_tags(game: &PgnGame) -> Vec<(String, String)> {
+        let mut tags: Vec<(String, String)> = SEVEN_TAG_ROSTER
+            .iter()
+            .map(|&(name, default)| {
+                let value = match name {
+                    "Result" => game.result.to_string(),
+                    _ => game.tag(name).unwrap_or(default).to_string(),
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+        if game.start_position != Position::default() {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), game.start_position.to_fen()));
+        }
+        let written: Vec<String> = tags.iter().map(|(name, _)| name.clone()).collect();
+        tags.extend(
+            game.tags
+                .iter()
+                .filter(|(name, _)| !written.contains(name) && name != "SetUp" && name != "FEN")
+                .cloned(),
+        );
+        tags
+    }
+
+    fn escape(value: &str) -> String {
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+
+    // Appends the movetext of 'moves' to 'tokens'. 'first_ply' counts the plies before the
+    // first move, so white moves on even plies.
+    fn line_tokens(tokens: &mut Vec<String>, moves: &[PgnMove], first_ply: usize) {
+        // Black moves need a move number at the start of a line and after any interruption.
+        let mut needs_number = true;
+        for (index, pgn_move) in moves.iter().enumerate() {
+            let ply = first_ply + index;
+            if let Some(comment) = &pgn_move.comment_before {
+                PgnWriter::comment_tokens(tokens, comment);
+                needs_number = true;
+            }
+            if ply.is_multiple_of(2) {
+                tokens.push(format!("{}.", ply / 2 + 1));
+            } else if needs_number {
+                tokens.push(format!("{}...", ply / 2 + 1));
+            }
+            tokens.push(pgn_move.san.clone());
+            tokens.extend(pgn_move.nags.iter().map(|nag| format!("${}", nag)));
+            needs_number = false;
+
+            if let Some(comment) = &pgn_move.comment_after {
+                PgnWriter::comment_tokens(tokens, comment);
+                needs_number = true;
+            }
+            for variation in pgn_move.variations.iter().filter(|v| !v.is_empty()) {
+                let start = tokens.len();
+                PgnWriter::line_tokens(tokens, variation, ply);
+                PgnWriter::enclose(tokens, start, '(', ')');
+                needs_number = true;
+            }
+        }
+    }
+
+    // Comments are split into words so that long ones can be wrapped.
+    fn comment_tokens(tokens: &mut Vec<String>, comment: &str) {
+        let start = tokens.len();
+        tokens.extend(
+            comment
+                .replace('}', "")
+                .split_whitespace()
+                .map(|word| word.to_string()),
+        );
+        if tokens.len() == start {
+            tokens.push(String::new());
+        }
+        PgnWriter::enclose(tokens, start, '{', '}');
+    }
+
+    fn enclose(tokens: &mut [String], start: usize, open: char, close: char) {
+        tokens[start].insert(0, open);
+        if let Some(last) = tokens.last_mut() {
+            last.push(close);
+        }
+    }
+
+    fn wrap(tokens: &[String]) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(token);
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Square;
+
+    fn read_all(pgn: &str) -> Vec<Result<PgnGame, PgnError>> {
+        PgnReader::new(pgn.as_bytes()).collect()
+    }
+
+    fn sans(moves: &[PgnMove]) -> Vec<&str> {
+        moves.iter().map(|pgn_move| pgn_move.san.as_str()).collect()
+    }
+
+    const TWO_GAMES: &str = r#"; A small database
+[Event "Casual game"]
+[Site "London"]
+[White "Anderssen, \"The Immortal\""]
+[Black "Kieseritzky"]
+[Result "1-0"]
+
+{Opening comment} 1. e4 e5 2. f4 exf4 3. Bc4!? $14 Qh4+ 4. Kf1 b5?! (4... Nf6
+(4... d6) 5. Nc3) 5. Bxb5 {A multi-line
+comment} Nf6 1-0
+
+[Event "Scholar's mate"]
+[Result "1-0"]
+
+1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6?? 4. Qxf7# 1-0
+"#;
+
+    #[test]
+    fn test_read_games() {
+        let games = read_all(TWO_GAMES);
+        assert_eq!(games.len(), 2);
+
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(game.tags.len(), 5);
+        assert_eq!(game.tag("White"), Some("Anderssen, \"The Immortal\""));
+        assert_eq!(game.tag("Round"), None);
+        assert_eq!(game.result, PgnResult::WhiteWins);
+        assert_eq!(
+            sans(&game.moves),
+            ["e4", "e5", "f4", "exf4", "Bc4", "Qh4+", "Kf1", "b5", "Bxb5", "Nf6"]
+        );
+        assert_eq!(
+            game.moves[0].comment_before.as_deref(),
+            Some("Opening comment")
+        );
+        assert_eq!(game.moves[4].nags, [5, 14]);
+        assert_eq!(game.moves[7].nags, [6]);
+        assert_eq!(
+            game.moves[8].comment_after.as_deref(),
+            Some("A multi-line comment")
+        );
+        assert_eq!(game.moves[5].mv.from, Square::D8);
+
+        let variations = &game.moves[7].variations;
+        assert_eq!(variations.len(), 1);
+        assert_eq!(sans(&variations[0]), ["Nf6", "Nc3"]);
+        assert_eq!(sans(&variations[0][0].variations[0]), ["d6"]);
+
+        let game = games[1].as_ref().unwrap();
+        assert_eq!(game.tag("Event"), Some("Scholar's mate"));
+        assert_eq!(game.moves.len(), 7);
+        assert_eq!(game.moves[5].nags, [4]);
+        assert_eq!(game.moves[6].san, "Qxf7#");
+        assert_eq!(
+            game.final_position().to_fen(),
+            "r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4"
+        );
+    }
+
+    #[test]
+    fn test_read_fen_tag() {
+        let pgn = "[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]\n\n1... Kd7 2. e4 *";
+        let games = read_all(pgn);
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(
+            game.start_position,
+            Position::load_position_from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap()
+        );
+        assert_eq!(sans(&game.moves), ["Kd7", "e4"]);
+        assert_eq!(game.result, PgnResult::Unknown);
+    }
+
+    #[test]
+    fn test_read_without_result() {
+        let games = read_all("1. d4 d5\n");
+        let game = games[0].as_ref().unwrap();
+        assert!(game.tags.is_empty());
+        assert_eq!(sans(&game.moves), ["d4", "d5"]);
+        assert_eq!(game.result, PgnResult::Unknown);
+        assert!(read_all("\n\n; nothing here\n").is_empty());
+    }
+
+    #[test]
+    fn test_errors_are_recoverable() {
+        let pgn = "[Event \"1\"]\n\n1. e4 e5 *\n\n\
+                   [Event \"2\"]\n\n1. e4 e5\n2. Ke3 *\n\n\
+                   [Event \"3\"]\n\n1. d4 (1. c4 *\n\n\
+                   [Event \"4\"]\n\n1. c4 1/2-1/2\n\n\
+                   [Event \"5\"]\n\n1. d4 {never closed\n\n[Event \"6\"]\n";
+        let games = read_all(pgn);
+        assert_eq!(games.len(), 5);
+        assert_eq!(games[0].as_ref().unwrap().tag("Event"), Some("1"));
+        assert_eq!(
+            games[1],
+            Err(PgnError::new(
+                8,
+                PgnErrorKind::InvalidMove(SanError::IllegalMove("Ke3".to_string()))
+            ))
+        );
+        assert_eq!(
+            games[2],
+            Err(PgnError::new(
+                12,
+                PgnErrorKind::UnexpectedToken("*".to_string())
+            ))
+        );
+        assert_eq!(games[3].as_ref().unwrap().result, PgnResult::Draw);
+        // An unclosed comment swallows the rest of the file.
+        assert_eq!(
+            games[4],
+            Err(PgnError::new(20, PgnErrorKind::UnterminatedComment))
+        );
+    }
+
+    #[test]
+    fn test_invalid_tags() {
+        let games = read_all("[Event \"x]\n1. e4 *\n");
+        assert_eq!(
+            games[0],
+            Err(PgnError::new(1, PgnErrorKind::UnterminatedString))
+        );
+        let games = read_all("[Event x]\n1. e4 *\n");
+        assert_eq!(games[0], Err(PgnError::new(1, PgnErrorKind::InvalidTag)));
+        let games = read_all("[FEN \"8/8 w\"]\n*\n");
+        assert!(matches!(
+            games[0],
+            Err(PgnError {
+                line: 1,
+                kind: PgnErrorKind::InvalidFen(_)
+            })
+        ));
+    }
+
+    #[test]
+    fn test_error_display() {
+        let error = PgnError::new(3, PgnErrorKind::UnterminatedVariation);
+        assert_eq!(
+            error.to_string(),
+            "invalid PGN on line 3: variation is never closed"
+        );
+    }
+
+    #[test]
+    fn test_write_game() {
+        let games = read_all(TWO_GAMES);
+        let pgn = PgnWriter::to_pgn(games[0].as_ref().unwrap());
+        assert_eq!(
+            pgn,
+            "[Event \"Casual game\"]\n\
+             [Site \"London\"]\n\
+             [Date \"????.??.??\"]\n\
+             [Round \"?\"]\n\
+             [White \"Anderssen, \\\"The Immortal\\\"\"]\n\
+             [Black \"Kieseritzky\"]\n\
+             [Result \"1-0\"]\n\
+             \n\
+             {Opening comment} 1. e4 e5 2. f4 exf4 3. Bc4 $5 $14 Qh4+ 4. Kf1 b5 $6 (4... Nf6\n\
+             (4... d6) 5. Nc3) 5. Bxb5 {A multi-line comment} 5... Nf6 1-0\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn test_write_from_fen() {
+        let games = read_all("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]\n\n1... Kd7 2. e4 *");
+        let pgn = PgnWriter::to_pgn(games[0].as_ref().unwrap());
+        assert!(pgn.contains(
+            "[Result \"*\"]\n[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]\n\n1... Kd7 2. e4 *\n"
+        ));
+
+        // Numbering carries on from the fullmove number in the FEN tag.
+        let games = read_all("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 3 41\"]\n\n41... Kd7 42. e4 *");
+        let pgn = PgnWriter::to_pgn(games[0].as_ref().unwrap());
+        assert!(pgn.contains("\n\n41... Kd7 42. e4 *\n"));
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let mut games: Vec<PgnGame> = read_all(TWO_GAMES)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        let long_comment = "word ".repeat(40);
+        games[1].moves[0].comment_after = Some(long_comment.trim().to_string());
+
+        let mut pgn = Vec::new();
+        for game in &games {
+            PgnWriter::write(&mut pgn, game).unwrap();
+        }
+        let text = String::from_utf8(pgn).unwrap();
+        assert!(text.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+
+        let read_back: Vec<PgnGame> = read_all(&text).into_iter().map(Result::unwrap).collect();
+        assert_eq!(read_back.len(), 2);
+        for (game, copy) in games.iter().zip(&read_back) {
+            assert_eq!(copy.moves, game.moves);
+            assert_eq!(copy.result, game.result);
+            assert_eq!(copy.start_position, game.start_position);
+            for (name, value) in &game.tags {
+                assert_eq!(copy.tag(name), Some(value.as_str()));
+            }
+        }
+    }
+}
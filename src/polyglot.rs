@@ -0,0 +1,363 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::board::File;
+use crate::board::Pieces;
+use crate::board::Position;
+use crate::board::Rank;
+use crate::board::Square;
+use crate::move_generator::Move;
+use crate::move_generator::MoveGenerator;
+
+// Size of one book entry on disk.
+const ENTRY_SIZE: usize = 16;
+
+// Polyglot position keys and move encoding, as used by Polyglot .bin opening books.
+pub struct Polyglot;
+
+impl Polyglot {
+    // The Polyglot Zobrist key of 'position'. Positions keep their key up to date in
+    // State, and it is computed with the Polyglot random numbers, so this is just that key.
+    pub fn key(position: &Position) -> u64 {
+        position.state.zobrist_key
+    }
+
+    // Packs 'mv' the way books store it: bits 0-5 hold the to square and bits 6-11 the from
+    // square, both numbered from A1, and bits 12-14 the promotion piece. Castling is
+    // written as the king capturing its own rook, e.g. e1h1.
+    pub fn encode_move(mv: Move) -> u16 {
+        let mut to = mv.to;
+        if mv.castling {
+            let file = if mv.to.file() == File::G {
+                File::H
+            } else {
+                File::A
+            };
+            to = Square::from_file_and_rank(file, mv.to.rank());
+        }
+        let promotion = mv.promotion.unwrap_or(Pieces::PAWN);
+        Polyglot::square_index(to) | Polyglot::square_index(mv.from) << 6 | (promotion as u16) << 12
+    }
+
+    // Finds the legal move in 'position' that the book move 'encoded' describes.
+    pub fn decode_move(position: &Position, encoded: u16) -> Option<Move> {
+        let from = Polyglot::square_at(encoded >> 6);
+        let mut to = Polyglot::square_at(encoded);
+        let promotion = match (encoded >> 12) & 0b111 {
+            0 => None,
+            piece @ 1..=4 => Some(piece as usize),
+            _ => return None,
+        };
+
+        let side = position.state.side_to_move;
+        let king_on_home_square = position.find_piece_on(side, from) == Some(Pieces::KING)
+            && from.file() == File::E
+            && from.rank() == to.rank();
+        if king_on_home_square && (to.file() == File::H || to.file() == File::A) {
+            let file = if to.file() == File::H {
+                File::G
+            } else {
+                File::C
+            };
+            to = Square::from_file_and_rank(file, to.rank());
+        }
+
+        MoveGenerator::generate_available_moves(position)
+            .into_iter()
+            .find(|mv| mv.from == from && mv.to == to && mv.promotion == promotion)
+    }
+
+    fn square_index(square: Square) -> u16 {
+        (square.rank().to_usize() * 8 + square.file().to_usize()) as u16
+    }
+
+    fn square_at(bits: u16) -> Square {
+        let index = (bits & 0b111111) as usize;
+        Square::from_file_and_rank(File::ALL[index % 8], Rank::ALL[index / 8])
+    }
+}
+
+// One record of a book: a position key, a move played in it and how good the move is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    // Entries are stored as 16 big-endian bytes.
+    pub fn from_bytes(bytes: &[u8; ENTRY_SIZE]) -> Self {
+        Self {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookError {
+    // The book file could not be read.
+    Io(String),
+    // The book's size isn't a whole number of entries.
+    InvalidLength(usize),
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BookError::Io(error) => write!(f, "could not read book: {}", error),
+            BookError::InvalidLength(length) => write!(
+                f,
+                "book size {} is not a multiple of {} bytes",
+                length, ENTRY_SIZE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BookError {}
+
+// A Polyglot opening book held in memory, with its entries sorted by key.
+pub struct PolyglotBook {
+    entries: Vec<BookEntry>,
+}
+
+impl PolyglotBook {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, BookError> {
+        let bytes = fs::read(path).map_err(|error| BookError::Io(error.to_string()))?;
+        PolyglotBook::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(BookError::InvalidLength(bytes.len()));
+        }
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| BookEntry::from_bytes(chunk.try_into().unwrap()))
+            .collect();
+        // Books are sorted already, but a stable sort keeps a hand-made one usable too.
+        entries.sort_by_key(|entry| entry.key);
+        Ok(Self { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // All entries for 'key', found by binary search.
+    pub fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    // The book moves for 'position' with their weights, in book order. Entries that don't
+    // decode to a legal move are skipped.
+    pub fn moves(&self, position: &Position) -> Vec<(Move, u16)> {
+        self.entries(Polyglot::key(position))
+            .iter()
+            .filter_map(|entry| {
+                Polyglot::decode_move(position, entry.mv).map(|mv| (mv, entry.weight))
+            })
+            .collect()
+    }
+
+    // The book move with the highest weight. The first one wins a tie.
+    pub fn best_move(&self, position: &Position) -> Option<Move> {
+        self.moves(position)
+            .into_iter()
+            .filter(|&(_, weight)| weight > 0)
+            .fold(None, |best: Option<(Move, u16)>, (mv, weight)| match best {
+                Some((_, best_weight)) if best_weight >= weight => best,
+                _ => Some((mv, weight)),
+            })
+            .map(|(mv, _)| mv)
+    }
+
+    // Picks a book move with probability proportional to its weight. 'random' should be a
+    // uniformly random number, so callers can use whichever generator they like.
+    pub fn weighted_move(&self, position: &Position, random: u64) -> Option<Move> {
+        let moves = self.moves(position);
+        let total: u64 = moves.iter().map(|&(_, weight)| weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = random % total;
+        for (mv, weight) in moves {
+            if pick < weight as u64 {
+                return Some(mv);
+            }
+            pick -= weight as u64;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uci::Uci;
+
+    fn play(position: &mut Position, moves: &[&str]) {
+        for uci in moves {
+            let mv = Uci::parse(position, uci).unwrap();
+            position.make_move(mv);
+        }
+    }
+
+    #[test]
+    fn test_key_reference_values() {
+        // The test positions from the Polyglot book format description.
+        let lines: [(&[&str], u64); 9] = [
+            (&[], 0x463b96181691fc9c),
+            (&["e2e4"], 0x823c9b50fd114196),
+            (&["e2e4", "d7d5"], 0x0756b94461c50fb0),
+            (&["e2e4", "d7d5", "e4e5"], 0x662fafb965db29d4),
+            (&["e2e4", "d7d5", "e4e5", "f7f5"], 0x22a48b5a8e47ff78),
+            (
+                &["e2e4", "d7d5", "e4e5", "f7f5", "e1e2"],
+                0x652a607ca3f242c1,
+            ),
+            (
+                &["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"],
+                0x00fdd303c946bdd9,
+            ),
+            (
+                &["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"],
+                0x3c8123ea7b067637,
+            ),
+            (
+                &["a2a4", "b7b5", "h2h4", "b5b4", "c2c4", "b4c3", "a1a3"],
+                0x5c3f9b829b279560,
+            ),
+        ];
+        for (moves, expected) in lines {
+            let mut position = Position::default();
+            play(&mut position, moves);
+            assert_eq!(Polyglot::key(&position), expected, "after {:?}", moves);
+        }
+    }
+
+    #[test]
+    fn test_move_encoding() {
+        let position =
+            Position::load_position_from_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        for mv in MoveGenerator::generate_available_moves(&position) {
+            assert_eq!(
+                Polyglot::decode_move(&position, Polyglot::encode_move(mv)),
+                Some(mv)
+            );
+        }
+        let short_castle = Uci::parse(&position, "e1g1").unwrap();
+        // e1h1, the king takes its own rook.
+        assert_eq!(Polyglot::encode_move(short_castle), 7 | 4 << 6);
+        let long_castle = Uci::parse(&position, "e1c1").unwrap();
+        assert_eq!(Polyglot::encode_move(long_castle), 4 << 6);
+        let promotion = Uci::parse(&position, "b7a8n").unwrap();
+        assert_eq!(Polyglot::encode_move(promotion), 56 | 49 << 6 | 1 << 12);
+        // A rook on e1 moving to h1 is a plain move, not castling.
+        let position = Position::load_position_from_fen("4k3/8/8/8/8/8/8/K3R3 w - - 0 1").unwrap();
+        assert_eq!(
+            Polyglot::decode_move(&position, 7 | 4 << 6).map(Uci::format),
+            Some("e1h1".to_string())
+        );
+    }
+
+    // Builds a small book with three moves from the start position and one reply to 1. e4.
+    fn small_book() -> Vec<u8> {
+        let start = Position::default();
+        let mut after_e4 = start.clone();
+        play(&mut after_e4, &["e2e4"]);
+        let entry = |position: &Position, uci: &str, weight: u16| BookEntry {
+            key: Polyglot::key(position),
+            mv: Polyglot::encode_move(Uci::parse(position, uci).unwrap()),
+            weight,
+            learn: 0,
+        };
+        let mut entries = [
+            entry(&start, "e2e4", 30),
+            entry(&start, "d2d4", 20),
+            entry(&start, "g1f3", 10),
+            entry(&start, "a2a3", 0),
+            entry(&after_e4, "c7c5", 5),
+        ];
+        entries.sort_by_key(|entry| entry.key);
+        entries.iter().flat_map(|entry| entry.to_bytes()).collect()
+    }
+
+    #[test]
+    fn test_book_probing() {
+        let path = std::env::temp_dir().join(format!("oxibrawler-{}.bin", std::process::id()));
+        fs::write(&path, small_book()).unwrap();
+        let book = PolyglotBook::open(&path);
+        fs::remove_file(&path).unwrap();
+        let book = book.unwrap();
+        assert_eq!(book.len(), 5);
+
+        let start = Position::default();
+        let moves: Vec<(String, u16)> = book
+            .moves(&start)
+            .into_iter()
+            .map(|(mv, weight)| (Uci::format(mv), weight))
+            .collect();
+        assert_eq!(moves.len(), 4);
+        assert!(moves.contains(&("d2d4".to_string(), 20)));
+        assert_eq!(
+            book.best_move(&start).map(Uci::format),
+            Some("e2e4".to_string())
+        );
+
+        // The weights split 0..60 into e4, d4 and Nf3 in book order, and a3 is never played.
+        let mut counts = std::collections::HashMap::new();
+        for random in 0..60 {
+            let mv = book.weighted_move(&start, random).unwrap();
+            *counts.entry(Uci::format(mv)).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts["e2e4"], 30);
+        assert_eq!(counts["d2d4"], 20);
+        assert_eq!(counts["g1f3"], 10);
+
+        let mut position = start.clone();
+        play(&mut position, &["e2e4"]);
+        assert_eq!(
+            book.best_move(&position).map(Uci::format),
+            Some("c7c5".to_string())
+        );
+        play(&mut position, &["c7c5"]);
+        assert_eq!(book.best_move(&position), None);
+        assert_eq!(book.weighted_move(&position, 7), None);
+    }
+
+    #[test]
+    fn test_book_errors() {
+        assert_eq!(
+            PolyglotBook::from_bytes(&[0; 17]).err(),
+            Some(BookError::InvalidLength(17))
+        );
+        assert!(matches!(
+            PolyglotBook::open("/nonexistent/book.bin"),
+            Err(BookError::Io(_))
+        ));
+        assert!(PolyglotBook::from_bytes(&[]).unwrap().is_empty());
+    }
+}
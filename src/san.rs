@@ -0,0 +1,343 @@
+use std::fmt;
+
+use crate::board::File;
+use crate::board::Pieces;
+use crate::board::Position;
+use crate::board::Rank;
+use crate::board::Square;
+use crate::move_generator::Move;
+use crate::move_generator::MoveGenerator;
+
+// SAN letters for each piece type. Pawns have no letter.
+const PIECE_LETTERS: [&str; 6] = ["", "N", "B", "R", "Q", "K"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    // The text isn't shaped like a SAN move.
+    InvalidSyntax(String),
+    // No legal move matches the text.
+    IllegalMove(String),
+    // More than one legal move matches the text. Holds the fully disambiguated candidates.
+    AmbiguousMove(String, Vec<String>),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::InvalidSyntax(san) => write!(f, "'{}' is not a valid SAN move", san),
+            SanError::IllegalMove(san) => write!(f, "'{}' is not a legal move here", san),
+            SanError::AmbiguousMove(san, candidates) => write!(
+                f,
+                "'{}' is ambiguous, it could be any of {}",
+                san,
+                candidates.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+// Standard Algebraic Notation, the move format used by PGN and by people.
+pub struct San;
+
+impl San {
+    // Writes 'mv' in SAN. 'mv' must be a legal move in 'position'.
+    pub fn format(position: &Position, mv: Move) -> String {
+        let legal_moves = MoveGenerator::generate_available_moves(position);
+        let mut san = San::format_without_suffix(mv, &legal_moves);
+
+        let mut after = position.clone();
+        after.make_move(mv);
+        if after.in_check() {
+            if MoveGenerator::generate_available_moves(&after).is_empty() {
+                san.push('#');
+            } else {
+                san.push('+');
+            }
+        }
+        san
+    }
+
+    // Finds the legal move in 'position' that 'san' describes. Check and mate suffixes and
+    // annotations such as '!' or '?!' are optional, and so is the 'x' of captures.
+    pub fn parse(position: &Position, san: &str) -> Result<Move, SanError> {
+        let legal_moves = MoveGenerator::generate_available_moves(position);
+        let invalid = || SanError::InvalidSyntax(san.to_string());
+        let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+
+        if let Some(king_side) = match text {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        } {
+            return legal_moves
+                .into_iter()
+                .find(|mv| mv.castling && (mv.to.file() == File::G) == king_side)
+                .ok_or(SanError::IllegalMove(san.to_string()));
+        }
+
+        let mut chars: Vec<char> = text.chars().collect();
+        let piece = match chars.first().and_then(|&c| San::piece_from_letter(c)) {
+            Some(piece) => {
+                chars.remove(0);
+                piece
+            }
+            None => Pieces::PAWN,
+        };
+
+        // Promotions are usually written "e8=Q", but "e8Q" is common enough to accept.
+        let mut promotion = None;
+        if piece == Pieces::PAWN {
+            if let Some(&last) = chars.last() {
+                if let Some(promoted) = San::piece_from_letter(last) {
+                    chars.pop();
+                    if chars.last() == Some(&'=') {
+                        chars.pop();
+                    }
+                    promotion = Some(promoted);
+                }
+            }
+        }
+
+        if chars.len() < 2 {
+            return Err(invalid());
+        }
+        let to: String = chars[chars.len() - 2..].iter().collect();
+        let to: Square = to.parse().map_err(|_| invalid())?;
+        chars.truncate(chars.len() - 2);
+        if chars.last() == Some(&'x') {
+            chars.pop();
+        }
+
+        // What is left is the optional file and rank of the origin square.
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in chars {
+            match (File::from_char(c), Rank::from_char(c)) {
+                (Some(file), _) if from_file.is_none() && from_rank.is_none() => {
+                    from_file = Some(file)
+                }
+                (_, Some(rank)) if from_rank.is_none() => from_rank = Some(rank),
+                _ => return Err(invalid()),
+            }
+        }
+
+        let candidates: Vec<Move> = legal_moves
+            .iter()
+            .copied()
+            .filter(|mv| {
+                mv.piece == piece
+                    && mv.to == to
+                    && mv.promotion == promotion
+                    && !mv.castling
+                    && from_file.is_none_or(|file| mv.from.file() == file)
+                    && from_rank.is_none_or(|rank| mv.from.rank() == rank)
+            })
+            .collect();
+
+        match candidates.len() {
+            0 => Err(SanError::IllegalMove(san.to_string())),
+            1 => Ok(candidates[0]),
+            _ => Err(SanError::AmbiguousMove(
+                san.to_string(),
+                candidates
+                    .iter()
+                    .map(|&mv| San::format_without_suffix(mv, &legal_moves))
+                    .collect(),
+            )),
+        }
+    }
+
+    fn format_without_suffix(mv: Move, legal_moves: &[Move]) -> String {
+        if mv.castling {
+            return if mv.to.file() == File::G {
+                "O-O".to_string()
+            } else {
+                "O-O-O".to_string()
+            };
+        }
+
+        let mut san = PIECE_LETTERS[mv.piece].to_string();
+        if mv.piece == Pieces::PAWN {
+            if mv.capture.is_some() {
+                san.push(mv.from.file().to_char());
+            }
+        } else {
+            // Name just enough of the origin square to tell this move apart from other
+            // moves of the same piece type to the same square: the file if that's enough,
+            // otherwise the rank, and both if neither is enough on its own.
+            let rivals: Vec<Square> = legal_moves
+                .iter()
+                .filter(|other| {
+                    other.piece == mv.piece && other.to == mv.to && other.from != mv.from
+                })
+                .map(|other| other.from)
+                .collect();
+            if !rivals.is_empty() {
+                let same_file = rivals.iter().any(|square| square.file() == mv.from.file());
+                let same_rank = rivals.iter().any(|square| square.rank() == mv.from.rank());
+                if !same_file {
+                    san.push(mv.from.file().to_char());
+                } else if !same_rank {
+                    san.push(mv.from.rank().to_char());
+                } else {
+                    san.push_str(&mv.from.to_string());
+                }
+            }
+        }
+
+        if mv.capture.is_some() {
+            san.push('x');
+        }
+        san.push_str(&mv.to.to_string());
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push_str(PIECE_LETTERS[promotion]);
+        }
+        san
+    }
+
+    fn piece_from_letter(letter: char) -> Option<usize> {
+        PIECE_LETTERS
+            .iter()
+            .position(|&piece| !piece.is_empty() && piece.starts_with(letter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(fen: &str) -> Position {
+        Position::load_position_from_fen(fen).unwrap()
+    }
+
+    fn format_all(position: &Position) -> Vec<String> {
+        MoveGenerator::generate_available_moves(position)
+            .into_iter()
+            .map(|mv| San::format(position, mv))
+            .collect()
+    }
+
+    #[test]
+    fn test_format_start_position() {
+        let moves = format_all(&Position::default());
+        assert!(moves.contains(&"e4".to_string()));
+        assert!(moves.contains(&"Nf3".to_string()));
+        assert!(moves.contains(&"a3".to_string()));
+    }
+
+    #[test]
+    fn test_format_disambiguation() {
+        let moves = format_all(&position(
+            "rnbqkb1r/ppp1pppp/5n2/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1",
+        ));
+        assert!(moves.contains(&"Nbd7".to_string()));
+        assert!(moves.contains(&"Nfd7".to_string()));
+        assert!(moves.contains(&"Nc6".to_string()));
+
+        let moves = format_all(&position("k7/8/8/8/8/4R3/8/4R1K1 w - - 0 1"));
+        assert!(moves.contains(&"R1e2".to_string()));
+        assert!(moves.contains(&"R3e2".to_string()));
+
+        let moves = format_all(&position("8/7k/8/8/8/Q7/8/Q1Q3K1 w - - 0 1"));
+        assert!(moves.contains(&"Qa1b2".to_string()));
+        assert!(moves.contains(&"Q3b2".to_string()));
+        assert!(moves.contains(&"Qcb2".to_string()));
+    }
+
+    #[test]
+    fn test_format_captures_and_suffixes() {
+        let position =
+            position("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4");
+        let moves = format_all(&position);
+        assert!(moves.contains(&"Qxf7#".to_string()));
+        assert!(moves.contains(&"Bxf7+".to_string()));
+        assert!(moves.contains(&"Qxe5+".to_string()));
+    }
+
+    #[test]
+    fn test_format_castling_and_promotions() {
+        let moves = format_all(&position("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"));
+        assert!(moves.contains(&"O-O".to_string()));
+        assert!(moves.contains(&"O-O-O".to_string()));
+
+        let moves = format_all(&position("3n4/4P3/8/8/8/8/8/K6k w - - 0 1"));
+        assert!(moves.contains(&"e8=Q".to_string()));
+        assert!(moves.contains(&"e8=N".to_string()));
+        assert!(moves.contains(&"exd8=R".to_string()));
+
+        let moves = format_all(&position("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1"));
+        assert!(moves.contains(&"exd6".to_string()));
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1",
+            "8/7k/8/8/8/Q7/8/Q1Q3K1 w - - 0 1",
+        ];
+        for fen in fens {
+            let position = position(fen);
+            for mv in MoveGenerator::generate_available_moves(&position) {
+                let san = San::format(&position, mv);
+                assert_eq!(San::parse(&position, &san), Ok(mv), "{} {}", fen, san);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_lenient_input() {
+        let position = position("3n4/4P3/8/8/8/8/8/K6k w - - 0 1");
+        let promotion = San::parse(&position, "exd8=Q").unwrap();
+        assert_eq!(San::parse(&position, "exd8Q"), Ok(promotion));
+        assert_eq!(San::parse(&position, "ed8=Q+"), Ok(promotion));
+        assert_eq!(San::parse(&position, "exd8=Q!?"), Ok(promotion));
+
+        let castling = San::parse(
+            &self::position("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"),
+            "0-0",
+        );
+        assert_eq!(castling.unwrap().to, Square::G1);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let position = position("rnbqkb1r/ppp1pppp/5n2/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1");
+        assert_eq!(
+            San::parse(&position, "Nd7"),
+            Err(SanError::AmbiguousMove(
+                "Nd7".to_string(),
+                vec!["Nfd7".to_string(), "Nbd7".to_string()]
+            ))
+        );
+        assert_eq!(
+            San::parse(&position, "Ke5"),
+            Err(SanError::IllegalMove("Ke5".to_string()))
+        );
+        assert_eq!(
+            San::parse(&position, "O-O"),
+            Err(SanError::IllegalMove("O-O".to_string()))
+        );
+        assert_eq!(
+            San::parse(&position, "Nz9"),
+            Err(SanError::InvalidSyntax("Nz9".to_string()))
+        );
+        assert_eq!(
+            San::parse(&position, ""),
+            Err(SanError::InvalidSyntax("".to_string()))
+        );
+        assert_eq!(
+            San::parse(&position, "N1bd7"),
+            Err(SanError::InvalidSyntax("N1bd7".to_string()))
+        );
+        assert_eq!(
+            San::parse(&position, "Nd7").unwrap_err().to_string(),
+            "'Nd7' is ambiguous, it could be any of Nfd7, Nbd7"
+        );
+    }
+}
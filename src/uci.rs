@@ -0,0 +1,211 @@
+use std::fmt;
+
+use crate::board::Pieces;
+use crate::board::Position;
+use crate::board::Square;
+use crate::move_generator::Move;
+use crate::move_generator::MoveGenerator;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciError {
+    // The text isn't shaped like a UCI move.
+    InvalidSyntax(String),
+    // No legal move matches the text.
+    IllegalMove(String),
+}
+
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UciError::InvalidSyntax(uci) => write!(f, "'{}' is not a valid UCI move", uci),
+            UciError::IllegalMove(uci) => write!(f, "'{}' is not a legal move here", uci),
+        }
+    }
+}
+
+impl std::error::Error for UciError {}
+
+// Long algebraic notation as used by the UCI protocol: the from and to squares, followed by
+// the promotion piece in lowercase, e.g. "e2e4", "e7e8q". Castling is written as the king's
+// two-square move, e.g. "e1g1", or in Chess960 mode as the king capturing its own rook,
+// e.g. "e1h1".
+pub struct Uci;
+
+impl Uci {
+    pub fn format(mv: Move) -> String {
+        let mut uci = String::with_capacity(5);
+        uci.push_str(&mv.from.to_string());
+        uci.push_str(&mv.to.to_string());
+        if let Some(promotion) = mv.promotion {
+            uci.push(['p', 'n', 'b', 'r', 'q', 'k'][promotion]);
+        }
+        uci
+    }
+
+    // Writes castling as the king capturing its own rook, as UCI does in Chess960 mode. In
+    // Chess960 the king can castle onto a square it could also step to, so the king's move
+    // alone doesn't tell castling apart. Other moves are written as by Uci::format.
+    pub fn format_chess960(position: &Position, mv: Move) -> String {
+        match position.castling_rook_move(mv) {
+            Some((rook, _)) => Uci::format(Move { to: rook, ..mv }),
+            None => Uci::format(mv),
+        }
+    }
+
+    // Finds the legal move in 'position' that 'uci' describes. The moving piece and the
+    // capture, castling and en passant flags are taken from the board. Castling may be
+    // written either way, though a king step that is also a legal move is read as the step.
+    pub fn parse(position: &Position, uci: &str) -> Result<Move, UciError> {
+        let invalid = || UciError::InvalidSyntax(uci.to_string());
+        let chars: Vec<char> = uci.trim().chars().collect();
+        if chars.len() != 4 && chars.len() != 5 {
+            return Err(invalid());
+        }
+        let square = |index: usize| -> Result<Square, UciError> {
+            let name: String = chars[index..index + 2].iter().collect();
+            name.parse().map_err(|_| invalid())
+        };
+        let (from, to) = (square(0)?, square(2)?);
+        let promotion = match chars.get(4) {
+            None => None,
+            Some('n') => Some(Pieces::KNIGHT),
+            Some('b') => Some(Pieces::BISHOP),
+            Some('r') => Some(Pieces::ROOK),
+            Some('q') => Some(Pieces::QUEEN),
+            Some(_) => return Err(invalid()),
+        };
+
+        let moves = MoveGenerator::generate_available_moves(position);
+        let castles_with_rook_on = |mv: &Move| {
+            position
+                .castling_rook_move(*mv)
+                .is_some_and(|(rook, _)| rook == to)
+        };
+        moves
+            .iter()
+            .find(|mv| mv.from == from && mv.to == to && mv.promotion == promotion && !mv.castling)
+            .or_else(|| {
+                moves.iter().find(|mv| {
+                    mv.castling
+                        && mv.from == from
+                        && promotion.is_none()
+                        && (mv.to == to || castles_with_rook_on(mv))
+                })
+            })
+            .copied()
+            .ok_or(UciError::IllegalMove(uci.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_fen(fen: &str) -> Position {
+        Position::load_position_from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            Uci::format(Move::new(Pieces::PAWN, Square::E2, Square::E4)),
+            "e2e4"
+        );
+        assert_eq!(
+            Uci::format(Move {
+                promotion: Some(Pieces::QUEEN),
+                ..Move::new(Pieces::PAWN, Square::E7, Square::E8)
+            }),
+            "e7e8q"
+        );
+        assert_eq!(
+            Uci::format(Move {
+                castling: true,
+                ..Move::new(Pieces::KING, Square::E1, Square::G1)
+            }),
+            "e1g1"
+        );
+        assert_eq!(
+            Uci::format(Move::new(Pieces::KNIGHT, Square::H1, Square::G3)),
+            "h1g3"
+        );
+    }
+
+    #[test]
+    fn test_parse_resolves_flags() {
+        let mv = Uci::parse(&Position::default(), "g1f3").unwrap();
+        assert_eq!(mv, Move::new(Pieces::KNIGHT, Square::G1, Square::F3));
+
+        let position = from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let mv = Uci::parse(&position, "e1c1").unwrap();
+        assert!(mv.castling);
+        assert_eq!(mv.piece, Pieces::KING);
+
+        let position = from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        let mv = Uci::parse(&position, "e5d6").unwrap();
+        assert!(mv.en_passant);
+        assert_eq!(mv.capture, Some(Pieces::PAWN));
+
+        let position = from_fen("3n4/4P3/8/8/8/8/8/K6k w - - 0 1");
+        let mv = Uci::parse(&position, "e7d8n").unwrap();
+        assert_eq!(mv.promotion, Some(Pieces::KNIGHT));
+        assert_eq!(mv.capture, Some(Pieces::KNIGHT));
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1",
+        ];
+        for fen in fens {
+            let position = from_fen(fen);
+            for mv in MoveGenerator::generate_available_moves(&position) {
+                assert_eq!(Uci::parse(&position, &Uci::format(mv)), Ok(mv));
+            }
+        }
+    }
+
+    #[test]
+    fn test_chess960_castling() {
+        // The king on f1 can step to g1 or castle there with the rook on h1.
+        let position = from_fen("4k3/8/8/8/8/8/8/1R3K1R w HB - 0 1");
+        let moves = MoveGenerator::generate_available_moves(&position);
+        let castles: Vec<Move> = moves.iter().copied().filter(|mv| mv.castling).collect();
+        assert_eq!(castles.len(), 2);
+        for mv in castles {
+            let uci = Uci::format_chess960(&position, mv);
+            assert!(uci == "f1h1" || uci == "f1b1", "{}", uci);
+            assert_eq!(Uci::parse(&position, &uci), Ok(mv));
+        }
+
+        let step = Uci::parse(&position, "f1g1").unwrap();
+        assert!(!step.castling);
+        assert_eq!(Uci::format_chess960(&position, step), "f1g1");
+
+        // Standard castling reads the same either way.
+        let position = from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let castle = Uci::parse(&position, "e1g1").unwrap();
+        assert_eq!(Uci::parse(&position, "e1h1"), Ok(castle));
+        assert_eq!(Uci::format_chess960(&position, castle), "e1h1");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let position = Position::default();
+        assert_eq!(
+            Uci::parse(&position, "e2e5"),
+            Err(UciError::IllegalMove("e2e5".to_string()))
+        );
+        assert_eq!(
+            Uci::parse(&position, "e7e8q"),
+            Err(UciError::IllegalMove("e7e8q".to_string()))
+        );
+        for invalid in ["", "e2", "e2e4e", "i2i4", "e0e1", "e7e8k", "e2-e4"] {
+            assert_eq!(
+                Uci::parse(&position, invalid),
+                Err(UciError::InvalidSyntax(invalid.to_string()))
+            );
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+
+use crate::board::{Castling, Pieces, Position, Rank, Sides, Square};
+
+// A reason why a position can't occur in a legal game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    // A side doesn't have exactly one king. Holds the side and how many kings it has.
+    KingCount(usize, u32),
+    // A pawn stands on the first or eighth rank.
+    PawnOnBackRank(Square),
+    // The side that just moved left its king in check.
+    OpponentInCheck,
+    // A castling right is set but the king isn't on its back rank on the right side of the
+    // rook. Holds the right.
+    CastlingWithoutKing(u8),
+    // A castling right is set but its rook isn't there. Holds the right.
+    CastlingWithoutRook(u8),
+    // No pawn can have double-pushed over the en passant square.
+    InvalidEnPassantSquare(Square),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::KingCount(side, count) => {
+                let side = if *side == Sides::WHITE {
+                    "white"
+                } else {
+                    "black"
+                };
+                write!(f, "{} has {} kings instead of 1", side, count)
+            }
+            Violation::PawnOnBackRank(square) => write!(f, "pawn on {}", square),
+            Violation::OpponentInCheck => write!(f, "the side not to move is in check"),
+            Violation::CastlingWithoutKing(right) => write!(
+                f,
+                "castling right {} without the king on its back rank",
+                Violation::right_name(*right)
+            ),
+            Violation::CastlingWithoutRook(right) => write!(
+                f,
+                "castling right {} without its rook",
+                Violation::right_name(*right)
+            ),
+            Violation::InvalidEnPassantSquare(square) => {
+                write!(f, "no pawn can have just passed over {}", square)
+            }
+        }
+    }
+}
+
+impl Violation {
+    fn right_name(right: u8) -> char {
+        match right {
+            Castling::WHITE_OO => 'K',
+            Castling::WHITE_OOO => 'Q',
+            Castling::BLACK_OO => 'k',
+            _ => 'q',
+        }
+    }
+}
+
+impl Position {
+    // Checks the position for things that can't happen in a legal game. The move generator
+    // assumes none of them, so positions from outside the program should be checked before
+    // searching them. Returns every violation found, or an empty list for a valid position.
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        for side in [Sides::WHITE, Sides::BLACK] {
+            let kings = self.bb_pieces[side][Pieces::KING].count();
+            if kings != 1 {
+                violations.push(Violation::KingCount(side, kings));
+            }
+        }
+
+        let pawns =
+            self.bb_pieces[Sides::WHITE][Pieces::PAWN] | self.bb_pieces[Sides::BLACK][Pieces::PAWN];
+        violations.extend(
+            Square::iter()
+                .filter(|&square| matches!(square.rank(), Rank::R1 | Rank::R8))
+                .filter(|&square| pawns.is_bit_set(square))
+                .map(Violation::PawnOnBackRank),
+        );
+
+        let side = self.state.side_to_move;
+        if let Some(king) = self.king_square(side ^ 1) {
+            if self.is_square_attacked(king, side) {
+                violations.push(Violation::OpponentInCheck);
+            }
+        }
+
+        // Chess960 lets the king start anywhere on its back rank, as long as the rook it
+        // castles with is on the matching side of it.
+        let castling_rights = &self.state.castling_rights;
+        for right in Castling::ALL {
+            if !castling_rights.has(right) {
+                continue;
+            }
+            let owner = Castling::side_of(right);
+            let rook = castling_rights.rook_square(right);
+            let king_in_place = self.king_square(owner).is_some_and(|king| {
+                king.rank() == rook.rank()
+                    && (king.file() < rook.file()) == (right & Castling::KING_SIDE != 0)
+                    && king.file() != rook.file()
+            });
+            if !king_in_place {
+                violations.push(Violation::CastlingWithoutKing(right));
+            }
+            if !self.bb_pieces[owner][Pieces::ROOK].is_bit_set(rook) {
+                violations.push(Violation::CastlingWithoutRook(right));
+            }
+        }
+
+        if let Some(square) = self.state.en_passant_square {
+            if !self.is_valid_en_passant_square(square) {
+                violations.push(Violation::InvalidEnPassantSquare(square));
+            }
+        }
+
+        violations
+    }
+
+    // Whether an enemy pawn can just have moved two squares over 'square': the pawn stands
+    // right in front of it, and both 'square' and the square the pawn came from are empty.
+    fn is_valid_en_passant_square(&self, square: Square) -> bool {
+        let side = self.state.side_to_move;
+        let (rank, forward) = if side == Sides::WHITE {
+            (Rank::R6, 1)
+        } else {
+            (Rank::R3, -1)
+        };
+        if square.rank() != rank {
+            return false;
+        }
+        let (Some(pushed), Some(origin)) = (square.offset(0, -forward), square.offset(0, forward))
+        else {
+            return false;
+        };
+        let occupied = self.find_occupied();
+        self.bb_pieces[side ^ 1][Pieces::PAWN].is_bit_set(pushed)
+            && !occupied.is_bit_set(square)
+            && !occupied.is_bit_set(origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violations(fen: &str) -> Vec<Violation> {
+        Position::load_position_from_fen(fen).unwrap().validate()
+    }
+
+    #[test]
+    fn test_valid_positions() {
+        assert!(Position::default().validate().is_empty());
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "4k3/8/8/8/8/8/8/4K2R b K - 0 1",
+        ] {
+            assert_eq!(violations(fen), [], "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_kings() {
+        assert_eq!(
+            violations("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+            [Violation::KingCount(Sides::BLACK, 0)]
+        );
+        assert_eq!(
+            violations("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"),
+            [Violation::KingCount(Sides::WHITE, 2)]
+        );
+    }
+
+    #[test]
+    fn test_pawns_on_back_ranks() {
+        assert_eq!(
+            violations("P3k3/8/8/8/8/8/8/4K2p w - - 0 1"),
+            [
+                Violation::PawnOnBackRank(Square::A8),
+                Violation::PawnOnBackRank(Square::H1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_side_not_to_move_in_check() {
+        assert_eq!(violations("R3k3/8/8/8/8/8/8/4K3 b - - 0 1"), []);
+        assert_eq!(
+            violations("R3k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+            [Violation::OpponentInCheck]
+        );
+    }
+
+    #[test]
+    fn test_castling_rights() {
+        assert_eq!(
+            violations("r3k3/8/8/8/8/8/8/4K2R w KQkq - 0 1"),
+            [
+                Violation::CastlingWithoutRook(Castling::WHITE_OOO),
+                Violation::CastlingWithoutRook(Castling::BLACK_OO),
+            ]
+        );
+        assert_eq!(
+            violations("r6r/3k4/8/8/8/8/8/4K3 w k - 0 1"),
+            [Violation::CastlingWithoutKing(Castling::BLACK_OO)]
+        );
+    }
+
+    #[test]
+    fn test_en_passant_square() {
+        for fen in [
+            // Wrong rank for the side to move.
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e3 0 1",
+            // No pawn in front of the square.
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+            // Something stands on the square the pawn passed over.
+            "rnbqkbnr/ppp1pppp/3p4/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+        ] {
+            let square = Position::load_position_from_fen(fen)
+                .unwrap()
+                .state
+                .en_passant_square
+                .unwrap();
+            assert_eq!(
+                violations(fen),
+                [Violation::InvalidEnPassantSquare(square)],
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_violation_display() {
+        assert_eq!(
+            Violation::KingCount(Sides::WHITE, 2).to_string(),
+            "white has 2 kings instead of 1"
+        );
+        assert_eq!(
+            Violation::CastlingWithoutRook(Castling::BLACK_OOO).to_string(),
+            "castling right q without its rook"
+        );
+    }
+}